@@ -0,0 +1,123 @@
+/* Copyright (C) 2022 Purism SPC
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/*! Generates the registry of builtin layouts out of `data/keyboards`.
+ *
+ * Every layout gets parsed on the way,
+ * so that a broken one fails the build,
+ * instead of getting replaced by the fallback at runtime.
+ *
+ * This only checks the structure of the file.
+ * Keysyms, buttons and outlines can't be checked without xkbcommon,
+ * so those are left to the `test_layout_*` tests in `tests/meson.build`,
+ * which run `rs::tests::check_builtin_layout` on every builtin layout.
+ */
+
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+#[allow(dead_code)]
+#[path = "src/data/schema.rs"]
+mod schema;
+
+const KEYBOARDS: &str = "data/keyboards";
+const ALIASES: &str = "data/layout_aliases.yaml";
+const SCHEMA: &str = "src/data/schema.rs";
+
+/// Returns layout names, like "terminal/de", with their files.
+fn find_layouts(base: &Path, dir: &Path, layouts: &mut BTreeMap<String, PathBuf>) {
+    let entries = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("Can't read {:?}: {}", dir, e));
+    for entry in entries {
+        let path = entry.expect("Bad directory entry").path();
+        if path.is_dir() {
+            find_layouts(base, &path, layouts);
+        } else if path.extension() == Some(OsStr::new("yaml")) {
+            let name = path.strip_prefix(base).unwrap()
+                .with_extension("");
+            let name = name.to_str()
+                .unwrap_or_else(|| panic!("Layout name not UTF-8: {:?}", path));
+            layouts.insert(name.into(), path.clone());
+        }
+    }
+}
+
+/// Returns the problem, if any.
+/// Only the structure is checked, see the top of the file.
+fn check_layout(path: &Path) -> Option<String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => return Some(e.to_string()),
+    };
+    match serde_yaml::from_str::<schema::Layout>(&text) {
+        Err(e) => Some(e.to_string()),
+        Ok(layout) => match layout.views.contains_key("base") {
+            true => None,
+            false => Some("The \"base\" view is missing".into()),
+        },
+    }
+}
+
+fn main() {
+    // When building with meson, the manifest is in the build directory,
+    // and this file is given with an absolute path.
+    let root = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap())
+        .join(Path::new(file!()).parent().unwrap());
+    for path in [KEYBOARDS, ALIASES, SCHEMA] {
+        println!("cargo:rerun-if-changed={}", root.join(path).display());
+    }
+
+    let keyboards = root.join(KEYBOARDS);
+    let mut layouts = BTreeMap::new();
+    find_layouts(&keyboards, &keyboards, &mut layouts);
+
+    let mut problems: Vec<String> = layouts.iter()
+        .filter_map(|(name, path)| {
+            check_layout(path)
+                .map(|problem| format!("Layout {}: {}", name, problem))
+        })
+        .collect();
+
+    let aliases: BTreeMap<String, String> = serde_yaml::from_str(
+        &fs::read_to_string(root.join(ALIASES))
+            .unwrap_or_else(|e| panic!("Can't read {}: {}", ALIASES, e))
+    ).unwrap_or_else(|e| panic!("Bad {}: {}", ALIASES, e));
+
+    let mut entries: Vec<(String, PathBuf)> = layouts.clone().into_iter().collect();
+    for (alias, target) in aliases {
+        if layouts.contains_key(&alias) {
+            problems.push(format!("Alias {} hides the layout file", alias));
+        }
+        match layouts.get(&target) {
+            Some(path) => entries.push((alias, path.clone())),
+            None => problems.push(
+                format!("Alias {} points to missing layout {}", alias, target)
+            ),
+        }
+    }
+
+    if !problems.is_empty() {
+        panic!("Builtin layouts are broken:\n{}", problems.join("\n"));
+    }
+
+    let mut registry = String::from(
+        "/// Generated by build.rs: name, contents\n\
+        static KEYBOARDS: &[(&str, &str)] = &[\n"
+    );
+    for (name, path) in entries {
+        registry.push_str(&format!(
+            "    ({:?}, include_str!({:?})),\n",
+            name,
+            path.to_str().unwrap(),
+        ));
+    }
+    registry.push_str("];\n");
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("keyboards.rs");
+    fs::write(&out, registry)
+        .unwrap_or_else(|e| panic!("Can't write {:?}: {}", out, e));
+}
@@ -111,10 +111,19 @@ eek_gtk_keyboard_real_draw (GtkWidget *self,
 
         set_allocation_size (keyboard, priv->keyboard->layout,
             allocation.width, allocation.height);
-        eek_renderer_set_scale_factor (priv->renderer,
-                                       gtk_widget_get_scale_factor (self));
     }
 
+    // TODO: the fractional scale only decides the size of the panel.
+    // GTK 3 can only render into buffers of integer scale,
+    // so the buttons are drawn at the scale rounded up,
+    // and the compositor shrinks the buffer to fit, blurring it.
+    // Crisp buttons need the buffer at the fractional size
+    // with wp_viewporter setting the logical size,
+    // but GTK 3 owns the buffer and the surface.
+    // The scale may change as the panel moves between outputs.
+    eek_renderer_set_scale_factor (priv->renderer,
+                                   gtk_widget_get_scale_factor (self));
+
     eek_renderer_render_keyboard (priv->renderer, priv->render_geometry,
         priv->submission, cr, priv->keyboard);
     return FALSE;
@@ -345,6 +345,9 @@ eek_renderer_new (Layout  *keyboard,
     renderer->pcontext = pcontext;
     g_object_ref (renderer->pcontext);
     const char *purpose_class = "normal";
+    enum squeek_arrangement_kind kind = squeek_layout_get_kind(keyboard->layout);
+    /* Split layouts are styled like the wide ones they come from */
+    gboolean wide = kind == ARRANGEMENT_KIND_WIDE || kind == ARRANGEMENT_KIND_SPLIT;
 
     /* Create a style context for the layout */
     GtkWidgetPath *path = gtk_widget_path_new();
@@ -353,7 +356,7 @@ eek_renderer_new (Layout  *keyboard,
     renderer->view_context = gtk_style_context_new();
     gtk_style_context_set_path(renderer->view_context, path);
     gtk_widget_path_unref(path);
-    if (squeek_layout_get_kind(keyboard->layout) == ARRANGEMENT_KIND_WIDE) {
+    if (wide) {
         gtk_style_context_add_class(renderer->view_context, "wide");
     }
     gtk_style_context_add_class(renderer->view_context, (char*)&keyboard->style_name);
@@ -364,7 +367,7 @@ eek_renderer_new (Layout  *keyboard,
     /* Create a style context for the buttons */
     path = gtk_widget_path_new();
     gtk_widget_path_append_type(path, view_type());
-    if (squeek_layout_get_kind(keyboard->layout) == ARRANGEMENT_KIND_WIDE) {
+    if (wide) {
         gtk_widget_path_iter_add_class(path, -1, "wide");
     }
     /* Add style classes based on purpose */
@@ -146,6 +146,12 @@ void eekboard_context_service_set_layout(EekboardContextService *context, char *
     }
 }
 
+/// Makes the UI place the current layout on the panel again,
+/// e.g. after the one-handed mode changed.
+void eekboard_context_service_refresh_layout(EekboardContextService *context) {
+    g_object_notify (G_OBJECT(context), "keyboard");
+}
+
 static void eekboard_context_service_update_settings_layout(EekboardContextService *context) {
     g_autofree gchar *keyboard_layout = NULL;
     g_autofree gchar *keyboard_type = NULL;
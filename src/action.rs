@@ -50,6 +50,26 @@ pub enum Action {
     /// Erase a position behind the cursor
     Erase,
     ShowPreferences,
+    /// Start entering a character by its code point,
+    /// showing this view.
+    /// Cancels the entry when already entering.
+    UnicodeInput(View),
+    /// Add a digit to the code point being entered
+    HexDigit(u8),
+    /// Remove the last digit of the code point being entered
+    HexErase,
+    /// Submit the character of the entered code point
+    CommitUnicode,
+    /// Commit the composition candidate shown in this slot
+    Candidate(usize),
+    /// Show the previous page of composition candidates
+    PreviousCandidates,
+    /// Show the next page of composition candidates
+    NextCandidates,
+    /// Move the layout to the other side of the panel in one-handed mode
+    SwitchSide,
+    /// For buttons which only show something
+    Nothing,
 }
 
 impl Action {
@@ -3,21 +3,181 @@
  *
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
+
+/*! The `sm.puri.SqueekDebug` interface, for looking inside the running keyboard.
+ *
+ * The loop driver reports the state changes through a `Monitor`,
+ * and they get served as JSON.
+ *
+ * Like `osk`, this is driven by the glib main loop,
+ * which is the only place where signals can be sent from.
+ */
+
+use crate::data::loading::DataSource;
+use crate::event_loop;
+use crate::layout;
+use crate::logging;
 use crate::main;
 use crate::state;
 
-use std::thread;
-use zbus::{Connection, ObjectServer, dbus_interface, fdo};
+use glib::{ControlFlow, IOCondition, MainContext, Priority};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::convert::TryInto;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
+use zbus::{ObjectServer, dbus_interface, fdo};
+
+// Traits
+use crate::event_loop::ActorState;
+use crate::logging::Warn;
 
-use super::Void;
 
-use std::convert::TryInto;
+const NAME: &str = "sm.puri.SqueekDebug";
+const PATH: &str = "/sm/puri/SqueekDebug";
+const INTERFACE: &str = "sm.puri.SqueekDebug";
+
+/// What the loop driver tells the debug interface.
+/// Sent only in debug mode.
+pub enum Report {
+    Event(Value),
+    State(state::Application),
+}
+
+/// Passes the events and the resulting states to the main loop.
+#[derive(Clone)]
+pub struct Monitor(glib::Sender<Report>);
+
+impl Monitor {
+    /// Returns the receiving end for `init`.
+    pub fn new() -> (Self, glib::Receiver<Report>) {
+        let (sender, receiver) = MainContext::channel(Priority::default());
+        (Monitor(sender), receiver)
+    }
+
+    fn send(&self, report: Report) {
+        self.0.send(report)
+            .or_print(logging::Problem::Warning, "Can't send to debug interface");
+    }
+}
+
+impl event_loop::Monitor<state::Application> for Monitor {
+    fn report_event(&self, state: &state::Application, event: &state::Event) {
+        if state.debug_mode_enabled {
+            self.send(Report::Event(
+                describe_event(event, state.is_input_sensitive())
+            ));
+        }
+    }
+
+    /// Copying the whole state is too much to do for every event
+    /// when nobody is looking.
+    fn report_state(&self, state: &state::Application) {
+        if state.debug_mode_enabled {
+            self.send(Report::State(state.clone()));
+        }
+    }
+}
+
+/// Leaves only the name of the enum variant.
+fn get_variant_name(value: Value) -> Value {
+    match value {
+        Value::Object(variant) => variant.keys().next()
+            .map(|name| Value::String(name.clone()))
+            .unwrap_or(Value::Null),
+        value => value,
+    }
+}
+
+/// When the text field holds sensitive data,
+/// only the kind of the event gets through,
+/// because anyone on the session bus can listen.
+fn describe_event(event: &state::Event, sensitive: bool) -> Value {
+    let value = serde_json::to_value(event)
+        .unwrap_or_else(|e| Value::String(format!("Unserializable: {}", e)));
+    match sensitive {
+        true => get_variant_name(value),
+        false => value,
+    }
+}
+
+/// Like `describe_event`, but for the state and the outcome,
+/// which describe the text field in the `im` entry.
+fn hide_input_details(value: Value, sensitive: bool) -> Value {
+    match (sensitive, value) {
+        (true, Value::Object(mut fields)) => {
+            if let Some(im) = fields.get_mut("im") {
+                *im = get_variant_name(im.take());
+            }
+            Value::Object(fields)
+        },
+        (_, value) => value,
+    }
+}
+
+/// The layout most recently handed to the UI
+#[derive(Serialize)]
+struct LayoutReport {
+    name: String,
+    kind: layout::ArrangementKind,
+    source: DataSource,
+    view: String,
+}
+
+thread_local! {
+    static LAYOUT: RefCell<Option<LayoutReport>> = RefCell::new(None);
+}
+
+/// Remembers the layout for inspection.
+/// Must be called on the main loop, whenever the UI gets a new layout.
+pub fn set_layout(name: String, source: DataSource, layout: &layout::Layout) {
+    LAYOUT.with(|current| {
+        *current.borrow_mut() = Some(LayoutReport {
+            name,
+            kind: layout.shape.kind,
+            source,
+            view: layout.state.current_view.clone(),
+        });
+    });
+}
+
+/// Remembers the view which the layout switched to.
+/// Must be called on the main loop.
+pub fn set_view(view: &str) {
+    LAYOUT.with(|current| {
+        if let Some(current) = current.borrow_mut().as_mut() {
+            current.view = view.into();
+        }
+    });
+}
+
+fn to_json<T: Serialize>(value: &T) -> fdo::Result<String> {
+    serde_json::to_string(value)
+        .map_err(|e| fdo::Error::Failed(e.to_string()))
+}
 
+fn to_redacted_json<T: Serialize>(value: &T, sensitive: bool) -> fdo::Result<String> {
+    let value = serde_json::to_value(value)
+        .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+    to_json(&hide_input_details(value, sensitive))
+}
 
 /// Accepts commands controlling the debug mode
 struct Manager {
     sender: main::EventLoop,
     enabled: bool,
+    /// As of the last event in debug mode
+    state: Rc<RefCell<Option<state::Application>>>,
+}
+
+impl Manager {
+    fn get_current_state(&self) -> fdo::Result<state::Application> {
+        self.state.borrow().clone()
+            .ok_or_else(|| fdo::Error::Failed("No state yet. Is debug mode enabled?".into()))
+    }
 }
 
 #[dbus_interface(name = "sm.puri.SqueekDebug")]
@@ -29,6 +189,10 @@ impl Manager {
     #[dbus_interface(property, name = "Enabled")]
     fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
+        if !enabled {
+            // The state stops getting reported, and would get stale.
+            *self.state.borrow_mut() = None;
+        }
         self.sender
             .send(state::Event::Debug(
                 if enabled { Event::Enable }
@@ -36,37 +200,166 @@ impl Manager {
             ))
             .unwrap();
     }
-}
 
-fn start(mgr: Manager) -> Result<Void, Box<dyn std::error::Error>> {
-    let connection = Connection::new_session()?;
-    fdo::DBusProxy::new(&connection)?.request_name(
-        "sm.puri.SqueekDebug",
-        fdo::RequestNameFlags::ReplaceExisting.into(),
-    )?;
+    /// Writes down the events driving the state into the file,
+    /// to be replayed with `squeekboard-replay`.
+    fn start_recording(&self, path: &str) {
+        self.sender
+            .send(state::Event::Debug(Event::StartRecording(path.into())))
+            .unwrap();
+    }
 
-    let mut object_server = ObjectServer::new(&connection);
-    object_server.at(&"/sm/puri/SqueekDebug".try_into()?, mgr)?;
+    fn stop_recording(&self) {
+        self.sender
+            .send(state::Event::Debug(Event::StopRecording))
+            .unwrap();
+    }
 
-    loop {
-        if let Err(err) = object_server.try_handle_next() {
-            eprintln!("{}", err);
-        }
+    /// Takes the same form as the `SQUEEKBOARD_LOG` environment variable.
+    fn set_log_filter(&self, filter: &str) -> fdo::Result<()> {
+        let filter = logging::Filter::parse(filter)
+            .map_err(|e| fdo::Error::InvalidArgs(e.to_string()))?;
+        logging::set_filter(filter);
+        Ok(())
+    }
+
+    /// "terminal" or "journal"
+    fn set_log_output(&self, output: &str) -> fdo::Result<()> {
+        let output = logging::Output::from_name(output)
+            .ok_or_else(|| fdo::Error::InvalidArgs(
+                format!("Unknown log output: {}", output)
+            ))?;
+        logging::set_output(output);
+        Ok(())
+    }
+
+    #[dbus_interface(property, name = "LogFilter")]
+    fn log_filter(&self) -> String {
+        logging::get_filter().to_string()
+    }
+
+    #[dbus_interface(property, name = "LogOutput")]
+    fn log_output(&self) -> String {
+        logging::get_output().as_str().into()
+    }
+
+    fn get_state(&self) -> fdo::Result<String> {
+        let state = self.get_current_state()?;
+        to_redacted_json(&state, state.is_input_sensitive())
+    }
+
+    /// The outcome changes with time even without events,
+    /// so it's calculated for the moment of the call.
+    fn get_outcome(&self) -> fdo::Result<String> {
+        let state = self.get_current_state()?;
+        to_redacted_json(&state.get_outcome(Instant::now()), state.is_input_sensitive())
+    }
+
+    fn get_layout(&self) -> fdo::Result<String> {
+        LAYOUT.with(|current| {
+            let current = current.borrow();
+            let current = current.as_ref()
+                .ok_or_else(|| fdo::Error::Failed("No layout yet".into()))?;
+            to_json(current)
+        })
     }
 }
 
-pub fn init(sender: main::EventLoop) {
+/// Takes the name on the session bus,
+/// and starts serving the interface from the main loop.
+/// Must be called on the thread owning the default main context.
+pub fn init(
+    sender: main::EventLoop,
+    reports: glib::Receiver<Report>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let connection = super::connect_session_nonblocking()?;
+    fdo::DBusProxy::new(&connection)?.request_name(
+        NAME,
+        fdo::RequestNameFlags::ReplaceExisting.into(),
+    )?;
+
+    let state = Rc::new(RefCell::new(None));
     let mgr = Manager {
         sender,
         enabled: false,
+        state: state.clone(),
     };
-    thread::spawn(move || {
-        start(mgr).unwrap();
+    let mut object_server = ObjectServer::new(&connection);
+    object_server.at(&PATH.try_into()?, mgr)?;
+
+    glib::source::unix_fd_add_local(
+        connection.as_raw_fd(),
+        IOCondition::IN | IOCondition::HUP,
+        move |_fd, condition| {
+            if condition.contains(IOCondition::HUP) {
+                log_print!(logging::Level::Warning, "D-Bus connection closed");
+                return ControlFlow::Break;
+            }
+            super::handle_pending_messages(&mut object_server);
+            ControlFlow::Continue
+        },
+    );
+
+    reports.attach(None, move |report| {
+        match report {
+            Report::Event(event) => {
+                connection
+                    .emit_signal(None, PATH, INTERFACE, "Event", &(event.to_string(),))
+                    .or_print(logging::Problem::Warning, "Can't emit event");
+            },
+            Report::State(new_state) => {
+                *state.borrow_mut() = Some(new_state);
+            },
+        }
+        ControlFlow::Continue
     });
+    Ok(())
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Event {
     Enable,
     Disable,
+    StartRecording(PathBuf),
+    StopRecording,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::imservice::{ ContentHint, ContentPurpose };
+
+    #[test]
+    fn redacted_event() {
+        let event = state::Event::InputMethod(state::InputMethod::Active(
+            state::InputMethodDetails {
+                hint: ContentHint::SENSITIVE_DATA,
+                purpose: ContentPurpose::Password,
+            },
+        ));
+        assert_eq!(
+            describe_event(&event, true),
+            Value::String("InputMethod".into()),
+        );
+        assert_ne!(
+            describe_event(&event, false),
+            Value::String("InputMethod".into()),
+        );
+    }
+
+    #[test]
+    fn redacted_state() {
+        let now = Instant::now();
+        let state = state::Application {
+            im: state::InputMethod::Active(state::InputMethodDetails {
+                hint: ContentHint::SENSITIVE_DATA,
+                purpose: ContentPurpose::Password,
+            }),
+            ..state::Application::new(now)
+        };
+        let json = to_redacted_json(&state, state.is_input_sensitive()).unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["im"], Value::String("Active".into()));
+        assert!(!json.contains("Password"));
+    }
 }
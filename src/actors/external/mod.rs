@@ -7,8 +7,46 @@
 /*! Contains actors with custom event loops, not based off of the event_loop module. */
  
 pub mod debug;
+pub mod osk;
 #[cfg(feature = "zbus_v1_5")]
 pub mod screensaver;
 
+use crate::logging;
+use std::io;
+use zbus::{Connection, ObjectServer, fdo, handshake};
+
+/// Connects to the session bus with a non-blocking socket,
+/// so that the main loop can read all the pending messages without getting stuck.
+/// Method calls made on the connection still wait for the reply.
+fn connect_session_nonblocking() -> zbus::Result<Connection> {
+    let auth = handshake::ClientHandshake::new_session_nonblock()?
+        .blocking_finish()?;
+    let connection = Connection::new_authenticated_unix(auth);
+    // The bus expects a Hello before anything else.
+    let name = fdo::DBusProxy::new(&connection)?.hello()?;
+    connection.set_unique_name(name)
+        .map_err(|name| zbus::Error::Handshake(
+            format!("Unique name already set: {}", name)
+        ))?;
+    Ok(connection)
+}
+
+/// Handles the messages which arrived on the connection,
+/// until there are no more to read.
+fn handle_pending_messages(object_server: &mut ObjectServer) {
+    loop {
+        match object_server.try_handle_next() {
+            // Either handled, or not meant for the object server.
+            Ok(_) => {},
+            Err(zbus::Error::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => {
+                log_print!(logging::Level::Warning, "Can't handle D-Bus message: {:?}", e);
+                break;
+            },
+        }
+    }
+}
+
 /// The uninhabited type. Cannot be created or returned; means "will never return" as return type. Useful for infinite loops.
+#[cfg(feature = "zbus_v1_5")]
 enum Void {}
\ No newline at end of file
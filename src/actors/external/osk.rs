@@ -0,0 +1,282 @@
+/*
+ * Copyright (C) 2022 Purism SPC
+ *
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/*! The `sm.puri.OSK0` interface, through which the shell controls the keyboard.
+ *
+ * Calls get forwarded to the main state.
+ * The properties are updated from the main loop,
+ * according to the outcome of the state.
+ *
+ * Unlike the other external actors, this one is driven by the glib main loop.
+ * A blocking zbus connection can't send while another thread waits for messages on it,
+ * and the property changes need to be sent from the main loop.
+ */
+
+use crate::logging;
+use crate::main;
+use crate::popover;
+use crate::state;
+
+use glib::{ControlFlow, IOCondition};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::os::unix::io::AsRawFd;
+use std::rc::Rc;
+use zbus::{Connection, ObjectServer, dbus_interface, fdo};
+use zvariant::Value;
+
+// Traits
+use crate::logging::Warn;
+
+pub const NAME: &str = "sm.puri.OSK0";
+const PATH: &str = "/sm/puri/OSK0";
+const INTERFACE: &str = "sm.puri.OSK0";
+
+/// Values of the properties
+#[derive(Clone, Debug, Default)]
+struct Properties {
+    visible: bool,
+    current_layout: String,
+    /// Pairs of kind and name
+    available_layouts: Vec<(String, String)>,
+    height: u32,
+}
+
+/// Accepts commands from the shell
+struct Manager {
+    sender: main::EventLoop,
+    properties: Rc<RefCell<Properties>>,
+}
+
+impl Manager {
+    fn get_properties(&self) -> Properties {
+        self.properties.borrow().clone()
+    }
+}
+
+/// Local layouts are those not coming from the system settings
+const LOCAL_KIND: &str = "local";
+
+fn layout_to_pair(layout: popover::LayoutId) -> (String, String) {
+    match layout {
+        popover::LayoutId::System { kind, name } => (kind, name),
+        popover::LayoutId::Local(name) => (LOCAL_KIND.into(), name),
+    }
+}
+
+fn get_available_layouts() -> Vec<(String, String)> {
+    popover::get_available_layouts().into_iter()
+        .map(layout_to_pair)
+        .collect()
+}
+
+fn find_layout(kind: &str, name: &str) -> fdo::Result<popover::LayoutId> {
+    popover::get_available_layouts().into_iter()
+        .find(|layout| layout_to_pair(layout.clone()) == (kind.into(), name.into()))
+        .ok_or_else(|| fdo::Error::InvalidArgs(
+            format!("No layout {} of kind {}", name, kind)
+        ))
+}
+
+#[dbus_interface(name = "sm.puri.OSK0")]
+impl Manager {
+    fn set_visible(&self, visible: bool) {
+        self.sender
+            .send(state::Event::Visibility(
+                if visible { state::visibility::Event::ForceVisible }
+                else { state::visibility::Event::ForceHidden }
+            ))
+            .or_print(logging::Problem::Bug, "Can't send to state");
+    }
+
+    /// `kind` is "local" for layouts which don't come from the system,
+    /// otherwise the same as in the system settings.
+    fn set_layout(&self, kind: &str, name: &str) -> fdo::Result<()> {
+        let layout = find_layout(kind, name)?;
+        self.sender
+            .send(state::Event::OverlayChanged(layout.clone()))
+            .or_print(logging::Problem::Bug, "Can't send to state");
+        popover::set_visible_layout(&layout);
+        Ok(())
+    }
+
+    /// Returns the display name, language, script, author, and keywords.
+    /// Values missing from the layout are empty,
+    /// except the name, which comes from the system if needed.
+    fn get_layout_meta(&self, kind: &str, name: &str)
+        -> fdo::Result<(String, String, String, String, Vec<String>)>
+    {
+        let layout = find_layout(kind, name)?;
+        let display_name = popover::translate_layout_names(&vec![layout.clone()])
+            .remove(0).0;
+        let meta = popover::get_meta(&layout).unwrap_or_default();
+        Ok((
+            display_name,
+            meta.language.unwrap_or_default(),
+            meta.script.unwrap_or_default(),
+            meta.author.unwrap_or_default(),
+            meta.keywords,
+        ))
+    }
+
+    #[dbus_interface(property, name = "Visible")]
+    fn visible(&self) -> bool {
+        self.get_properties().visible
+    }
+
+    #[dbus_interface(property, name = "CurrentLayout")]
+    fn current_layout(&self) -> String {
+        self.get_properties().current_layout
+    }
+
+    #[dbus_interface(property, name = "AvailableLayouts")]
+    fn available_layouts(&self) -> Vec<(String, String)> {
+        self.get_properties().available_layouts
+    }
+
+    /// Allocated height of the panel in logical pixels, or 0 when hidden
+    #[dbus_interface(property, name = "Height")]
+    fn height(&self) -> u32 {
+        self.get_properties().height
+    }
+}
+
+/// Updates the properties from the main loop
+pub struct Handle {
+    connection: Connection,
+    properties: Rc<RefCell<Properties>>,
+}
+
+impl Handle {
+    pub fn set_visible(&self, visible: bool) {
+        let changed = {
+            let mut properties = self.properties.borrow_mut();
+            let changed = properties.visible != visible;
+            properties.visible = visible;
+            changed
+        };
+        if changed {
+            self.emit_changed("Visible", Value::from(visible));
+        }
+    }
+
+    pub fn set_current_layout(&self, name: String) {
+        let changed = {
+            let mut properties = self.properties.borrow_mut();
+            let changed = properties.current_layout != name;
+            properties.current_layout = name.clone();
+            changed
+        };
+        if changed {
+            self.emit_changed("CurrentLayout", Value::from(name));
+        }
+    }
+
+    /// Reads the layouts again.
+    /// They change together with the system input sources,
+    /// and the custom overlays.
+    pub fn update_available_layouts(&self) {
+        let layouts = get_available_layouts();
+        let changed = {
+            let mut properties = self.properties.borrow_mut();
+            let changed = properties.available_layouts != layouts;
+            properties.available_layouts = layouts.clone();
+            changed
+        };
+        if changed {
+            self.emit_changed("AvailableLayouts", Value::from(layouts));
+        }
+    }
+
+    pub fn set_height(&self, height: u32) {
+        let changed = {
+            let mut properties = self.properties.borrow_mut();
+            let changed = properties.height != height;
+            properties.height = height;
+            changed
+        };
+        if changed {
+            self.emit_changed("Height", Value::from(height));
+        }
+    }
+
+    fn emit_changed(&self, property: &str, value: Value) {
+        let mut changed = HashMap::new();
+        changed.insert(property, value);
+        self.connection
+            .emit_signal(
+                None,
+                PATH,
+                "org.freedesktop.DBus.Properties",
+                "PropertiesChanged",
+                &(INTERFACE, changed, Vec::<&str>::new()),
+            )
+            .or_print(logging::Problem::Warning, "Can't emit PropertiesChanged");
+    }
+}
+
+/// Why the interface is not available
+#[derive(Debug)]
+pub enum Error {
+    /// Another instance is probably running
+    NameTaken,
+    DBus(zbus::Error),
+}
+
+impl From<zbus::Error> for Error {
+    fn from(e: zbus::Error) -> Self {
+        Error::DBus(e)
+    }
+}
+
+impl From<fdo::Error> for Error {
+    fn from(e: fdo::Error) -> Self {
+        Error::DBus(e.into())
+    }
+}
+
+/// Takes the name on the session bus,
+/// and starts serving the interface from the main loop.
+/// Must be called on the thread owning the default main context.
+pub fn init(sender: main::EventLoop) -> Result<Handle, Error> {
+    let connection = super::connect_session_nonblocking()?;
+    let reply = fdo::DBusProxy::new(&connection)?.request_name(
+        NAME,
+        fdo::RequestNameFlags::DoNotQueue.into(),
+    )?;
+    match reply {
+        fdo::RequestNameReply::PrimaryOwner => {},
+        _ => return Err(Error::NameTaken),
+    }
+
+    let properties = Rc::new(RefCell::new(Properties {
+        available_layouts: get_available_layouts(),
+        ..Properties::default()
+    }));
+    let mgr = Manager {
+        sender,
+        properties: properties.clone(),
+    };
+    let mut object_server = ObjectServer::new(&connection);
+    object_server.at(&PATH.try_into().map_err(zbus::Error::Variant)?, mgr)?;
+
+    // Only read when there's something to read,
+    // so that the main loop doesn't block.
+    glib::source::unix_fd_add_local(
+        connection.as_raw_fd(),
+        IOCondition::IN | IOCondition::HUP,
+        move |_fd, condition| {
+            if condition.contains(IOCondition::HUP) {
+                log_print!(logging::Level::Warning, "D-Bus connection closed");
+                return ControlFlow::Break;
+            }
+            super::handle_pending_messages(&mut object_server);
+            ControlFlow::Continue
+        },
+    );
+    Ok(Handle { connection, properties })
+}
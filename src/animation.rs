@@ -4,18 +4,72 @@
 
 /*! Animation details */
 
-use std::time::Duration;
+use std::time::{ Duration, Instant };
 
 use crate::imservice::ContentPurpose;
 use crate::layout::ArrangementKind;
 use crate::outputs::OutputId;
-use crate::panel::PixelSize;
+use crate::panel::{ PixelSize, Placement };
+use crate::recording;
+use serde::{Deserialize, Serialize};
 
 /// The keyboard should hide after this has elapsed to prevent flickering.
 pub const HIDING_TIMEOUT: Duration = Duration::from_millis(200);
 
+/// Time to slide the panel all the way in or out.
+pub const SLIDE_DURATION: Duration = Duration::from_millis(150);
+
+/// Time between steps of the animation.
+pub const FRAME_DURATION: Duration = Duration::from_millis(16);
+
+/// The panel moving toward shown or hidden, at a constant speed.
+/// When the direction changes midway,
+/// the new slide starts from the part already shown.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Slide {
+    /// The direction of movement
+    pub shown: bool,
+    #[serde(with = "recording::instant")]
+    pub start: Instant,
+    /// The part of the panel shown at the start, from 0 to 1
+    pub from: f64,
+}
+
+impl Slide {
+    /// Fully hidden, without movement
+    pub fn hidden(now: Instant) -> Self {
+        Slide { shown: false, start: now, from: 0.0 }
+    }
+
+    /// The part of the panel shown at the given time, from 0 to 1
+    pub fn get_fraction(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.start).as_secs_f64()
+            / SLIDE_DURATION.as_secs_f64();
+        if self.shown {
+            (self.from + elapsed).min(1.0)
+        } else {
+            (self.from - elapsed).max(0.0)
+        }
+    }
+
+    /// When the panel stops moving
+    pub fn get_end(&self) -> Instant {
+        let remaining = if self.shown { 1.0 - self.from } else { self.from };
+        self.start + SLIDE_DURATION.mul_f64(remaining)
+    }
+
+    /// Starts moving in the given direction, unless already doing so.
+    pub fn turn(self, shown: bool, now: Instant) -> Self {
+        if shown == self.shown {
+            self
+        } else {
+            Slide { shown, start: now, from: self.get_fraction(now) }
+        }
+    }
+}
+
 /// Description of parameters which influence panel contents
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct Contents {
     pub name: String,
     pub kind: ArrangementKind,
@@ -23,12 +77,17 @@ pub struct Contents {
     pub purpose: ContentPurpose,
 }
 
-/// The outwardly visible state of visibility
-#[derive(PartialEq, Debug, Clone)]
+/// The outwardly visible state of visibility.
+/// While sliding, the panel is visible with a part of its height.
+/// The contents get shrunk to fit that part, rather than moved.
+#[derive(PartialEq, Debug, Clone, Serialize)]
 pub enum Outcome {
     Visible {
         output: OutputId,
         height: PixelSize,
+        /// The height when not sliding
+        full_height: PixelSize,
+        placement: Placement,
         contents: Contents,
     },
     Hidden,
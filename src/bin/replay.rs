@@ -0,0 +1,33 @@
+extern crate clap;
+extern crate rs;
+
+use std::path::Path;
+use std::process;
+
+fn main() {
+    #[cfg(feature = "clap_v4")]
+    let matches = clap::Command::new("squeekboard-replay")
+        .about("Replay events recorded through the debug interface, showing the resulting outcomes and commands.")
+        .arg(
+            clap::Arg::new("INPUT")
+                .required(true)
+                .help("File with the recorded events")
+        )
+        .get_matches();
+    #[cfg(feature = "clap_v4")]
+    let path = matches.get_one::<String>("INPUT").unwrap();
+
+    #[cfg(not(feature = "clap_v4"))]
+    let matches = clap_app!(replay =>
+        (name: "squeekboard-replay")
+        (about: "Replay events recorded through the debug interface, showing the resulting outcomes and commands.")
+        (@arg INPUT: +required "File with the recorded events")
+    ).get_matches();
+    #[cfg(not(feature = "clap_v4"))]
+    let path = matches.value_of("INPUT").unwrap();
+
+    if let Err(e) = rs::recording::replay(Path::new(path)) {
+        eprintln!("Replay failed: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,351 @@
+/* Copyright (C) 2022 Purism SPC
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/*! Controls the running Squeekboard over D-Bus. */
+
+extern crate clap;
+extern crate serde;
+extern crate serde_json;
+extern crate zbus;
+
+use serde::Serialize;
+use std::process;
+use zbus::{Connection, dbus_proxy, fdo};
+
+
+#[dbus_proxy(
+    interface = "sm.puri.OSK0",
+    default_service = "sm.puri.OSK0",
+    default_path = "/sm/puri/OSK0"
+)]
+trait OSK {
+    fn set_visible(&self, visible: bool) -> zbus::Result<()>;
+    fn set_layout(&self, kind: &str, name: &str) -> zbus::Result<()>;
+    #[dbus_proxy(property)]
+    fn visible(&self) -> fdo::Result<bool>;
+    #[dbus_proxy(property)]
+    fn current_layout(&self) -> fdo::Result<String>;
+    #[dbus_proxy(property)]
+    fn available_layouts(&self) -> fdo::Result<Vec<(String, String)>>;
+}
+
+#[dbus_proxy(
+    interface = "sm.puri.SqueekDebug",
+    default_service = "sm.puri.SqueekDebug",
+    default_path = "/sm/puri/SqueekDebug"
+)]
+trait SqueekDebug {
+    #[dbus_proxy(property)]
+    fn set_enabled(&self, enabled: bool) -> fdo::Result<()>;
+}
+
+/// The kind of layouts which don't come from the system settings
+const LOCAL_KIND: &str = "local";
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Report {
+    Visibility { visible: bool },
+    Layouts { current: String, layouts: Vec<Layout> },
+    Layout(Layout),
+    Debug { enabled: bool },
+}
+
+#[derive(Serialize)]
+struct Layout {
+    kind: String,
+    name: String,
+}
+
+impl Report {
+    fn print_human(&self) {
+        let on_off = |value| if value { "on" } else { "off" };
+        match self {
+            Report::Visibility { visible } => println!(
+                "Keyboard {}",
+                if *visible { "shown" } else { "hidden" },
+            ),
+            Report::Layouts { current, layouts } => {
+                for Layout { kind, name } in layouts {
+                    let marker = if name == current { '*' } else { ' ' };
+                    println!("{} {} ({})", marker, name, kind);
+                }
+            },
+            Report::Layout(Layout { kind, name })
+                => println!("Layout {} ({})", name, kind),
+            Report::Debug { enabled } => println!("Debug mode {}", on_off(*enabled)),
+        }
+    }
+}
+
+/// Finds the layout by name, preferring system layouts,
+/// because those come first.
+fn find_layout(proxy: &OSKProxy, name: &str) -> Result<Layout, String> {
+    proxy.available_layouts()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .find(|(_kind, layout_name)| layout_name == name)
+        .map(|(kind, name)| Layout { kind, name })
+        .ok_or_else(|| format!("No layout named {}", name))
+}
+
+fn set_layout(proxy: &OSKProxy, layout: Layout) -> Result<Report, String> {
+    proxy.set_layout(&layout.kind, &layout.name).map_err(|e| e.to_string())?;
+    Ok(Report::Layout(layout))
+}
+
+/// What the user asked for on the command line
+enum Request {
+    Show,
+    Hide,
+    Toggle,
+    ListLayouts,
+    SetLayout(String),
+    /// The overlay's name, or "none"
+    SetOverlay(String),
+    Debug { enabled: bool },
+}
+
+struct Args {
+    request: Request,
+    json: bool,
+}
+
+/// Finds a local layout, so that custom overlays work too.
+/// "none" means the current system layout, dropping the overlay.
+fn find_overlay(proxy: &OSKProxy, name: &str) -> Result<Layout, String> {
+    let layouts = proxy.available_layouts().map_err(|e| e.to_string())?;
+    match name {
+        "none" => layouts.into_iter()
+            .find(|(kind, _name)| kind != LOCAL_KIND)
+            .map(|(kind, name)| Layout { kind, name })
+            .ok_or_else(|| String::from("No system layout to return to")),
+        name => {
+            let overlays: Vec<String> = layouts.into_iter()
+                .filter(|(kind, _name)| kind == LOCAL_KIND)
+                .map(|(_kind, name)| name)
+                .collect();
+            if overlays.iter().any(|overlay| overlay == name) {
+                Ok(Layout { kind: LOCAL_KIND.into(), name: name.into() })
+            } else {
+                Err(format!(
+                    "No overlay named {}. Available: {}, none",
+                    name,
+                    overlays.join(", "),
+                ))
+            }
+        },
+    }
+}
+
+fn run(request: Request) -> Result<Report, String> {
+    let conn = Connection::new_session().map_err(|e| e.to_string())?;
+    let osk = || OSKProxy::new(&conn).map_err(|e| e.to_string());
+    let set_visible = |visible| {
+        osk()?.set_visible(visible).map_err(|e| e.to_string())?;
+        Ok(Report::Visibility { visible })
+    };
+
+    match request {
+        Request::Show => set_visible(true),
+        Request::Hide => set_visible(false),
+        Request::Toggle => {
+            let visible = osk()?.visible().map_err(|e| e.to_string())?;
+            set_visible(!visible)
+        },
+        Request::ListLayouts => {
+            let proxy = osk()?;
+            Ok(Report::Layouts {
+                current: proxy.current_layout().map_err(|e| e.to_string())?,
+                layouts: proxy.available_layouts()
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .map(|(kind, name)| Layout { kind, name })
+                    .collect(),
+            })
+        },
+        Request::SetLayout(name) => {
+            let proxy = osk()?;
+            let layout = find_layout(&proxy, &name)?;
+            set_layout(&proxy, layout)
+        },
+        Request::SetOverlay(name) => {
+            let proxy = osk()?;
+            let layout = find_overlay(&proxy, &name)?;
+            set_layout(&proxy, layout)
+        },
+        Request::Debug { enabled } => {
+            SqueekDebugProxy::new(&conn)
+                .map_err(|e| e.to_string())?
+                .set_enabled(enabled)
+                .map_err(|e| e.to_string())?;
+            Ok(Report::Debug { enabled })
+        },
+    }
+}
+
+const ABOUT: &str = "Controls the running Squeekboard.";
+const OVERLAY_HELP: &str
+    = "Name of the overlay, like \"emoji\", or \"none\" to go back to the system layout";
+
+#[cfg(feature = "clap_v4")]
+fn parse_args() -> Args {
+    use clap::{Arg, ArgAction, Command};
+
+    let matches = Command::new("squeekboardctl")
+        .about(ABOUT)
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("Print the result as JSON")
+        )
+        .subcommand_required(true)
+        .subcommand(Command::new("show").about("Show the keyboard"))
+        .subcommand(Command::new("hide").about("Hide the keyboard"))
+        .subcommand(Command::new("toggle").about("Show the keyboard if hidden, otherwise hide it"))
+        .subcommand(
+            Command::new("layout")
+                .about("Layouts from the system settings and built in overlays")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("List layouts, marking the current one"))
+                .subcommand(
+                    Command::new("set")
+                        .about("Switch to a layout")
+                        .arg(Arg::new("NAME").required(true).help("Name of the layout, like \"us\""))
+                )
+        )
+        .subcommand(
+            Command::new("overlay")
+                .about("Layouts replacing the current one, like emoji")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("set")
+                        .about("Switch to an overlay, or back to the system layout with \"none\"")
+                        .arg(Arg::new("OVERLAY").required(true).help(OVERLAY_HELP))
+                )
+        )
+        .subcommand(
+            Command::new("debug")
+                .about("Print changes to the internal state")
+                .arg(
+                    Arg::new("STATE")
+                        .required(true)
+                        .value_parser(["on", "off"])
+                )
+        )
+        .get_matches();
+
+    let request = match matches.subcommand() {
+        Some(("show", _)) => Request::Show,
+        Some(("hide", _)) => Request::Hide,
+        Some(("toggle", _)) => Request::Toggle,
+        Some(("layout", sub)) => match sub.subcommand() {
+            Some(("list", _)) => Request::ListLayouts,
+            Some(("set", args)) => Request::SetLayout(
+                args.get_one::<String>("NAME").unwrap().clone()
+            ),
+            _ => unreachable!(),
+        },
+        Some(("overlay", sub)) => match sub.subcommand() {
+            Some(("set", args)) => Request::SetOverlay(
+                args.get_one::<String>("OVERLAY").unwrap().clone()
+            ),
+            _ => unreachable!(),
+        },
+        Some(("debug", args)) => Request::Debug {
+            enabled: args.get_one::<String>("STATE").unwrap() == "on",
+        },
+        _ => unreachable!(),
+    };
+    Args { request, json: matches.get_flag("json") }
+}
+
+#[cfg(not(feature = "clap_v4"))]
+fn parse_args() -> Args {
+    use clap::{App, AppSettings, Arg, SubCommand};
+
+    let matches = App::new("squeekboardctl")
+        .about(ABOUT)
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Print the result as JSON")
+        )
+        .setting(AppSettings::SubcommandRequired)
+        .subcommand(SubCommand::with_name("show").about("Show the keyboard"))
+        .subcommand(SubCommand::with_name("hide").about("Hide the keyboard"))
+        .subcommand(SubCommand::with_name("toggle").about("Show the keyboard if hidden, otherwise hide it"))
+        .subcommand(
+            SubCommand::with_name("layout")
+                .about("Layouts from the system settings and built in overlays")
+                .setting(AppSettings::SubcommandRequired)
+                .subcommand(SubCommand::with_name("list").about("List layouts, marking the current one"))
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Switch to a layout")
+                        .arg(Arg::with_name("NAME").required(true).help("Name of the layout, like \"us\""))
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("overlay")
+                .about("Layouts replacing the current one, like emoji")
+                .setting(AppSettings::SubcommandRequired)
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Switch to an overlay, or back to the system layout with \"none\"")
+                        .arg(Arg::with_name("OVERLAY").required(true).help(OVERLAY_HELP))
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("debug")
+                .about("Print changes to the internal state")
+                .arg(
+                    Arg::with_name("STATE")
+                        .required(true)
+                        .possible_values(&["on", "off"])
+                )
+        )
+        .get_matches();
+
+    let request = match matches.subcommand() {
+        ("show", _) => Request::Show,
+        ("hide", _) => Request::Hide,
+        ("toggle", _) => Request::Toggle,
+        ("layout", Some(sub)) => match sub.subcommand() {
+            ("list", _) => Request::ListLayouts,
+            ("set", Some(args)) => Request::SetLayout(args.value_of("NAME").unwrap().into()),
+            _ => unreachable!(),
+        },
+        ("overlay", Some(sub)) => match sub.subcommand() {
+            ("set", Some(args)) => Request::SetOverlay(args.value_of("OVERLAY").unwrap().into()),
+            _ => unreachable!(),
+        },
+        ("debug", Some(args)) => Request::Debug {
+            enabled: args.value_of("STATE") == Some("on"),
+        },
+        _ => unreachable!(),
+    };
+    Args { request, json: matches.is_present("json") }
+}
+
+fn main() {
+    let Args { request, json } = parse_args();
+
+    match run(request) {
+        Ok(report) => {
+            if json {
+                println!("{}", serde_json::to_string(&report).unwrap());
+            } else {
+                report.print_human();
+            }
+        },
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        },
+    }
+}
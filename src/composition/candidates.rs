@@ -0,0 +1,290 @@
+/*! Composing text by picking from candidates found in a dictionary,
+ * as in Pinyin or Zhuyin input.
+ *
+ * The typed sequence stays in preedit,
+ * and gets replaced by the chosen candidate.
+ */
+
+use super::NotComposable;
+
+/// Maps typed sequences to candidates, in the order of preference.
+pub struct Dictionary(Vec<(String, Vec<String>)>);
+
+impl Dictionary {
+    /// Each line holds the sequence, followed by candidates,
+    /// separated by whitespace. Lines starting with "#" are ignored.
+    pub fn parse(data: &str) -> Dictionary {
+        Dictionary(
+            data.lines()
+                .filter(|line| !line.starts_with('#'))
+                .filter_map(|line| {
+                    let mut words = line.split_whitespace();
+                    let key = words.next()?;
+                    Some((key.into(), words.map(String::from).collect()))
+                })
+                .collect()
+        )
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> impl Iterator<Item=&'a String> {
+        self.0.iter()
+            .filter(move |(k, _)| k == key)
+            .flat_map(|(_, candidates)| candidates.iter())
+    }
+
+    /// Candidates for sequences which continue the key.
+    fn get_completions<'a>(&'a self, key: &'a str) -> impl Iterator<Item=&'a String> {
+        self.0.iter()
+            .filter(move |(k, _)| k.len() > key.len() && k.starts_with(key))
+            .flat_map(|(_, candidates)| candidates.iter())
+    }
+}
+
+/// Decides what belongs in the typed sequence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Script {
+    /// Pinyin, with "v" standing in for "ü"
+    Latin,
+    Bopomofo,
+}
+
+impl Script {
+    fn accepts(&self, c: char) -> bool {
+        match self {
+            Script::Latin => c.is_ascii_lowercase(),
+            Script::Bopomofo => {
+                ('\u{3105}'..='\u{312F}').contains(&c) || Script::is_tone(c)
+            },
+        }
+    }
+
+    /// Tone marks help the reader, but are not part of dictionary keys.
+    fn is_tone(c: char) -> bool {
+        ['ˊ', 'ˇ', 'ˋ', '˙'].contains(&c)
+    }
+
+    fn to_key(&self, input: &str) -> String {
+        input.chars().filter(|c| !Script::is_tone(*c)).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Candidate {
+    text: String,
+    /// Bytes of input replaced by the candidate
+    consumed: usize,
+}
+
+pub struct Composer {
+    script: Script,
+    dictionary: Dictionary,
+    input: String,
+    page: usize,
+    page_size: usize,
+}
+
+impl Composer {
+    pub fn new(script: Script, dictionary: Dictionary, page_size: usize) -> Composer {
+        Composer {
+            script,
+            dictionary,
+            input: String::new(),
+            page: 0,
+            page_size,
+        }
+    }
+
+    /// Candidates for the whole input come first,
+    /// followed by the ones for its longest known beginning.
+    fn get_candidates(&self) -> Vec<Candidate> {
+        if self.input.is_empty() {
+            return Vec::new();
+        }
+        let whole = |text: &String| Candidate {
+            text: text.clone(),
+            consumed: self.input.len(),
+        };
+        let key = self.script.to_key(&self.input);
+        let mut candidates: Vec<_> = self.dictionary.get(&key)
+            .chain(self.dictionary.get_completions(&key))
+            .map(whole)
+            .collect();
+
+        let prefix_ends = self.input.char_indices()
+            .map(|(i, _)| i)
+            .skip(1)
+            .collect::<Vec<_>>();
+        for end in prefix_ends.into_iter().rev() {
+            let key = self.script.to_key(&self.input[..end]);
+            let prefix_candidates: Vec<_> = self.dictionary.get(&key)
+                .map(|text| Candidate { text: text.clone(), consumed: end })
+                .collect();
+            if !prefix_candidates.is_empty() {
+                candidates.extend(prefix_candidates);
+                break;
+            }
+        }
+
+        let mut unique: Vec<Candidate> = Vec::new();
+        for candidate in candidates {
+            if !unique.iter().any(|c| c.text == candidate.text) {
+                unique.push(candidate);
+            }
+        }
+        unique
+    }
+
+    pub fn push(&mut self, text: &str) -> Result<String, NotComposable> {
+        if text.is_empty() || !text.chars().all(|c| self.script.accepts(c)) {
+            return Err(NotComposable);
+        }
+        self.input.push_str(text);
+        self.page = 0;
+        Ok(String::new())
+    }
+
+    pub fn pop(&mut self) -> bool {
+        self.page = 0;
+        self.input.pop().is_some()
+    }
+
+    pub fn get_preedit(&self) -> String {
+        self.input.clone()
+    }
+
+    /// Converts everything using the preferred candidates.
+    /// Parts without any are committed as typed.
+    pub fn flush(&mut self) -> String {
+        let mut text = String::new();
+        while !self.input.is_empty() {
+            match self.get_candidates().into_iter().next() {
+                Some(candidate) => {
+                    text.push_str(&candidate.text);
+                    self.input.drain(..candidate.consumed);
+                },
+                None => {
+                    text.push_str(&self.input);
+                    self.input.clear();
+                },
+            }
+        }
+        self.page = 0;
+        text
+    }
+
+    /// Returns the candidate for the slot on the current page.
+    pub fn get_candidate(&self, slot: usize) -> Option<String> {
+        if slot >= self.page_size {
+            return None;
+        }
+        self.get_candidates()
+            .into_iter()
+            .nth(self.page * self.page_size + slot)
+            .map(|c| c.text)
+    }
+
+    /// Returns the text to commit. The rest of input remains.
+    pub fn select_candidate(&mut self, slot: usize) -> Option<String> {
+        if slot >= self.page_size {
+            return None;
+        }
+        let candidate = self.get_candidates()
+            .into_iter()
+            .nth(self.page * self.page_size + slot)?;
+        self.input.drain(..candidate.consumed);
+        self.page = 0;
+        Some(candidate.text)
+    }
+
+    pub fn turn_page(&mut self, forward: bool) {
+        // Without candidate buttons, there are no pages.
+        if self.page_size == 0 {
+            return;
+        }
+        if forward {
+            let count = self.get_candidates().len();
+            if (self.page + 1) * self.page_size < count {
+                self.page += 1;
+            }
+        } else {
+            self.page = self.page.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn make_composer(script: Script, data: &str) -> Composer {
+        Composer::new(script, Dictionary::parse(data), 2)
+    }
+
+    #[test]
+    fn select() {
+        let mut c = make_composer(Script::Latin, "ni 你 呢\nnihao 你好\nhao 好");
+        assert_eq!(c.push("n"), Ok(String::new()));
+        // Completions
+        assert_eq!(c.get_candidate(0), Some("你".into()));
+        assert_eq!(c.get_candidate(1), Some("呢".into()));
+        c.push("i").unwrap();
+        c.push("h").unwrap();
+        assert_eq!(c.get_candidate(0), Some("你好".into()));
+        assert_eq!(c.get_candidate(1), Some("你".into()));
+        assert_eq!(c.select_candidate(1), Some("你".into()));
+        assert_eq!(c.get_preedit(), "h");
+        assert_eq!(c.get_candidate(0), Some("好".into()));
+    }
+
+    #[test]
+    fn paging() {
+        let mut c = make_composer(Script::Latin, "a 1 2 3");
+        c.push("a").unwrap();
+        c.turn_page(true);
+        assert_eq!(c.get_candidate(0), Some("3".into()));
+        assert_eq!(c.get_candidate(1), None);
+        c.turn_page(true);
+        assert_eq!(c.get_candidate(0), Some("3".into()));
+        c.turn_page(false);
+        assert_eq!(c.get_candidate(0), Some("1".into()));
+        assert_eq!(c.get_candidate(2), None);
+    }
+
+    #[test]
+    fn paging_without_slots() {
+        let mut c = Composer::new(Script::Latin, Dictionary::parse("a 1 2 3"), 0);
+        c.push("a").unwrap();
+        c.turn_page(true);
+        assert_eq!(c.page, 0);
+    }
+
+    #[test]
+    fn flush_segments() {
+        let mut c = make_composer(Script::Latin, "ni 你\nhao 好");
+        c.push("nihaox").unwrap();
+        assert_eq!(c.flush(), "你好x");
+        assert_eq!(c.get_preedit(), "");
+    }
+
+    #[test]
+    fn bopomofo_tones() {
+        let mut c = make_composer(Script::Bopomofo, "ㄋㄧ 你");
+        assert_eq!(c.push("a"), Err(NotComposable));
+        c.push("ㄋ").unwrap();
+        c.push("ㄧ").unwrap();
+        c.push("ˇ").unwrap();
+        assert_eq!(c.get_preedit(), "ㄋㄧˇ");
+        assert_eq!(c.select_candidate(0), Some("你".into()));
+        assert_eq!(c.get_preedit(), "");
+    }
+
+    #[test]
+    fn shipped_dictionaries() {
+        for name in &["pinyin", "zhuyin"] {
+            let data = crate::resources::get_dictionary(name).unwrap();
+            let dictionary = Dictionary::parse(data);
+            assert!(!dictionary.0.is_empty());
+            assert!(dictionary.0.iter().all(|(_, c)| !c.is_empty()));
+        }
+    }
+}
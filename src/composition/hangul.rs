@@ -0,0 +1,288 @@
+/*! Composing Hangul syllables out of jamo, as in the 2-set (dubeolsik) layout.
+ *
+ * Buttons submit compatibility jamo (U+3131 to U+3163),
+ * and those are assembled into precomposed syllables.
+ */
+
+use super::NotComposable;
+
+/// Leading consonants, in the order of syllable composition.
+const LEADS: &[char] = &[
+    'ㄱ', 'ㄲ', 'ㄴ', 'ㄷ', 'ㄸ', 'ㄹ', 'ㅁ', 'ㅂ', 'ㅃ', 'ㅅ',
+    'ㅆ', 'ㅇ', 'ㅈ', 'ㅉ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ',
+];
+
+const VOWELS: &[char] = &[
+    'ㅏ', 'ㅐ', 'ㅑ', 'ㅒ', 'ㅓ', 'ㅔ', 'ㅕ', 'ㅖ', 'ㅗ', 'ㅘ', 'ㅙ',
+    'ㅚ', 'ㅛ', 'ㅜ', 'ㅝ', 'ㅞ', 'ㅟ', 'ㅠ', 'ㅡ', 'ㅢ', 'ㅣ',
+];
+
+/// Trailing consonants. Index 0 in composition means "none".
+const TAILS: &[char] = &[
+    'ㄱ', 'ㄲ', 'ㄳ', 'ㄴ', 'ㄵ', 'ㄶ', 'ㄷ', 'ㄹ', 'ㄺ', 'ㄻ', 'ㄼ', 'ㄽ',
+    'ㄾ', 'ㄿ', 'ㅀ', 'ㅁ', 'ㅂ', 'ㅄ', 'ㅅ', 'ㅆ', 'ㅇ', 'ㅈ', 'ㅊ', 'ㅋ',
+    'ㅌ', 'ㅍ', 'ㅎ',
+];
+
+/// (first, second, combined)
+const VOWEL_PAIRS: &[(char, char, char)] = &[
+    ('ㅗ', 'ㅏ', 'ㅘ'),
+    ('ㅗ', 'ㅐ', 'ㅙ'),
+    ('ㅗ', 'ㅣ', 'ㅚ'),
+    ('ㅜ', 'ㅓ', 'ㅝ'),
+    ('ㅜ', 'ㅔ', 'ㅞ'),
+    ('ㅜ', 'ㅣ', 'ㅟ'),
+    ('ㅡ', 'ㅣ', 'ㅢ'),
+];
+
+/// (first, second, combined)
+const TAIL_PAIRS: &[(char, char, char)] = &[
+    ('ㄱ', 'ㅅ', 'ㄳ'),
+    ('ㄴ', 'ㅈ', 'ㄵ'),
+    ('ㄴ', 'ㅎ', 'ㄶ'),
+    ('ㄹ', 'ㄱ', 'ㄺ'),
+    ('ㄹ', 'ㅁ', 'ㄻ'),
+    ('ㄹ', 'ㅂ', 'ㄼ'),
+    ('ㄹ', 'ㅅ', 'ㄽ'),
+    ('ㄹ', 'ㅌ', 'ㄾ'),
+    ('ㄹ', 'ㅍ', 'ㄿ'),
+    ('ㄹ', 'ㅎ', 'ㅀ'),
+    ('ㅂ', 'ㅅ', 'ㅄ'),
+];
+
+const SYLLABLE_BASE: u32 = 0xAC00;
+
+fn index_of(table: &[char], c: char) -> Option<usize> {
+    table.iter().position(|t| *t == c)
+}
+
+fn is_vowel(c: char) -> bool {
+    index_of(VOWELS, c).is_some()
+}
+
+fn is_jamo(c: char) -> bool {
+    is_vowel(c) || index_of(LEADS, c).is_some() || index_of(TAILS, c).is_some()
+}
+
+fn combine(pairs: &[(char, char, char)], first: char, second: char) -> Option<char> {
+    pairs.iter()
+        .find(|(a, b, _)| *a == first && *b == second)
+        .map(|(_, _, c)| *c)
+}
+
+/// Splits a trailing consonant into what stays
+/// and what moves to the next syllable.
+fn split_tail(tail: char) -> (Option<char>, char) {
+    match TAIL_PAIRS.iter().find(|(_, _, c)| *c == tail) {
+        Some((a, b, _)) => (Some(*a), *b),
+        None => (None, tail),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Syllable {
+    lead: Option<char>,
+    vowel: Option<char>,
+    tail: Option<char>,
+}
+
+enum Next {
+    Same(Syllable),
+    /// The syllable is finished, and the next one starts with the strokes.
+    Split { done: Syllable, strokes: Vec<char> },
+}
+
+impl Syllable {
+    fn add(&self, jamo: char) -> Next {
+        let split = |done, strokes| Next::Split { done, strokes };
+        match (self.lead, self.vowel, self.tail, is_vowel(jamo)) {
+            (None, None, _, false) => Next::Same(Syllable {
+                lead: Some(jamo),
+                ..Syllable::default()
+            }),
+            (None, None, _, true) => Next::Same(Syllable {
+                vowel: Some(jamo),
+                ..Syllable::default()
+            }),
+            (Some(_), None, _, true) => Next::Same(Syllable {
+                vowel: Some(jamo),
+                ..*self
+            }),
+            // Double leads have their own buttons.
+            (Some(_), None, _, false) => split(*self, vec![jamo]),
+            (None, Some(_), _, false) => split(*self, vec![jamo]),
+            (_, Some(vowel), None, true) => match combine(VOWEL_PAIRS, vowel, jamo) {
+                Some(vowel) => Next::Same(Syllable { vowel: Some(vowel), ..*self }),
+                None => split(*self, vec![jamo]),
+            },
+            (Some(_), Some(_), None, false) => match index_of(TAILS, jamo) {
+                Some(_) => Next::Same(Syllable { tail: Some(jamo), ..*self }),
+                None => split(*self, vec![jamo]),
+            },
+            (Some(_), Some(_), Some(tail), false) => match combine(TAIL_PAIRS, tail, jamo) {
+                Some(tail) => Next::Same(Syllable { tail: Some(tail), ..*self }),
+                None => split(*self, vec![jamo]),
+            },
+            // The trailing consonant becomes the lead of the new syllable.
+            (_, Some(_), Some(tail), true) => {
+                let (kept, moved) = split_tail(tail);
+                split(Syllable { tail: kept, ..*self }, vec![moved, jamo])
+            },
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let composed = match (self.lead, self.vowel) {
+            (Some(lead), Some(vowel)) => {
+                let lead = index_of(LEADS, lead);
+                let vowel = index_of(VOWELS, vowel);
+                let tail = match self.tail {
+                    None => Some(0),
+                    Some(tail) => index_of(TAILS, tail).map(|i| i + 1),
+                };
+                match (lead, vowel, tail) {
+                    (Some(l), Some(v), Some(t)) => std::char::from_u32(
+                        SYLLABLE_BASE + ((l * VOWELS.len() + v) * (TAILS.len() + 1) + t) as u32
+                    ),
+                    _ => None,
+                }
+            },
+            _ => None,
+        };
+        match composed {
+            Some(c) => c.to_string(),
+            None => self.lead.iter()
+                .chain(self.vowel.iter())
+                .chain(self.tail.iter())
+                .collect(),
+        }
+    }
+}
+
+pub struct Composer {
+    /// Jamo entered into the current syllable, for undoing.
+    strokes: Vec<char>,
+    syllable: Syllable,
+}
+
+impl Composer {
+    pub fn new() -> Composer {
+        Composer {
+            strokes: Vec::new(),
+            syllable: Syllable::default(),
+        }
+    }
+
+    pub fn push(&mut self, text: &str) -> Result<String, NotComposable> {
+        if text.is_empty() || !text.chars().all(is_jamo) {
+            return Err(NotComposable);
+        }
+        let mut committed = String::new();
+        for jamo in text.chars() {
+            match self.syllable.add(jamo) {
+                Next::Same(syllable) => {
+                    self.syllable = syllable;
+                    self.strokes.push(jamo);
+                },
+                Next::Split { done, strokes } => {
+                    committed.push_str(&done.to_text());
+                    self.replay(strokes);
+                },
+            }
+        }
+        Ok(committed)
+    }
+
+    /// Decomposes the current syllable by one jamo.
+    pub fn pop(&mut self) -> bool {
+        let mut strokes = self.strokes.clone();
+        match strokes.pop() {
+            Some(_) => {
+                self.replay(strokes);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn get_preedit(&self) -> String {
+        self.syllable.to_text()
+    }
+
+    pub fn flush(&mut self) -> String {
+        let text = self.get_preedit();
+        self.replay(Vec::new());
+        text
+    }
+
+    fn replay(&mut self, strokes: Vec<char>) {
+        self.syllable = Syllable::default();
+        for jamo in &strokes {
+            // Strokes come from a single syllable, so they never split.
+            if let Next::Same(syllable) = self.syllable.add(*jamo) {
+                self.syllable = syllable;
+            }
+        }
+        self.strokes = strokes;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn type_jamo(composer: &mut Composer, jamo: &str) -> String {
+        jamo.chars()
+            .map(|j| composer.push(&j.to_string()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn compose_word() {
+        let mut c = Composer::new();
+        assert_eq!(type_jamo(&mut c, "ㅎㅏㄴ"), "");
+        assert_eq!(c.get_preedit(), "한");
+        assert_eq!(type_jamo(&mut c, "ㄱㅡㄹ"), "한");
+        assert_eq!(c.flush(), "글");
+        assert_eq!(c.get_preedit(), "");
+    }
+
+    #[test]
+    fn tail_moves_to_next_syllable() {
+        let mut c = Composer::new();
+        assert_eq!(type_jamo(&mut c, "ㄷㅏㄹㄱ"), "");
+        assert_eq!(c.get_preedit(), "닭");
+        assert_eq!(type_jamo(&mut c, "ㅏ"), "달");
+        assert_eq!(c.get_preedit(), "가");
+    }
+
+    #[test]
+    fn compound_vowel() {
+        let mut c = Composer::new();
+        type_jamo(&mut c, "ㅇㅗㅏ");
+        assert_eq!(c.get_preedit(), "와");
+    }
+
+    #[test]
+    fn decompose() {
+        let mut c = Composer::new();
+        type_jamo(&mut c, "ㄷㅏㄹㄱ");
+        assert!(c.pop());
+        assert_eq!(c.get_preedit(), "달");
+        assert!(c.pop());
+        assert_eq!(c.get_preedit(), "다");
+        assert!(c.pop());
+        assert_eq!(c.get_preedit(), "ㄷ");
+        assert!(c.pop());
+        assert_eq!(c.get_preedit(), "");
+        assert!(!c.pop());
+    }
+
+    #[test]
+    fn not_jamo() {
+        let mut c = Composer::new();
+        type_jamo(&mut c, "ㄱ");
+        assert_eq!(c.push("a"), Err(NotComposable));
+        assert_eq!(c.get_preedit(), "ㄱ");
+    }
+}
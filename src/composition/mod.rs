@@ -0,0 +1,151 @@
+/*! Composing text out of multiple button presses.
+ *
+ * Some scripts need several presses to produce a single character.
+ * The character in the making is shown as preedit,
+ * and gets committed once it can't change any more.
+ *
+ * This is a library module: it only transforms text,
+ * sending it is left to `submission`.
+ */
+
+mod candidates;
+mod hangul;
+mod transliteration;
+
+use std::fs;
+
+use crate::logging;
+use crate::resources;
+use crate::xdg;
+
+/// Composition engine chosen by the layout
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    Hangul,
+    /// Chinese in Latin script, converted through a dictionary
+    Pinyin,
+    /// Chinese in Bopomofo, converted through a dictionary
+    Zhuyin,
+    /// Words rewritten according to the named rules
+    Transliteration(String),
+}
+
+/// The text input doesn't belong to the script being composed.
+#[derive(Debug, PartialEq)]
+pub struct NotComposable;
+
+/// Holds the state of text being composed.
+pub enum Composer {
+    Hangul(hangul::Composer),
+    Candidates(candidates::Composer),
+    Transliteration(transliteration::Composer),
+}
+
+/// Rules placed by the user take precedence over built-in ones.
+fn load_rules(name: &str) -> transliteration::Rules {
+    let path = xdg::data_path("squeekboard/transliteration")
+        .map(|dir| dir.join(format!("{}.txt", name)));
+    let data = path.and_then(|path| {
+            fs::read_to_string(&path).ok()
+                .map(|data| (path.display().to_string(), data))
+        })
+        .or_else(|| resources::get_transliteration(name)
+            .map(|data| (format!("built-in {}", name), data.to_owned())));
+    let parsed = match data {
+        Some((source, data)) => transliteration::Rules::parse(&data)
+            .map_err(|e| format!("Bad transliteration rules in {}: {}", source, e)),
+        None => Err(format!("No transliteration rules {}", name)),
+    };
+    parsed.unwrap_or_else(|e| {
+        log_print!(logging::Level::Warning, "{}", e);
+        transliteration::Rules::default()
+    })
+}
+
+impl Composer {
+    /// The page size is the number of candidates shown at once.
+    pub fn new(kind: Kind, page_size: usize) -> Composer {
+        let with_dictionary = |script, name| {
+            let data = resources::get_dictionary(name)
+                .unwrap_or_else(|| {
+                    log_print!(logging::Level::Bug, "No dictionary {}", name);
+                    ""
+                });
+            Composer::Candidates(candidates::Composer::new(
+                script,
+                candidates::Dictionary::parse(data),
+                page_size,
+            ))
+        };
+        match kind {
+            Kind::Hangul => Composer::Hangul(hangul::Composer::new()),
+            Kind::Pinyin => with_dictionary(candidates::Script::Latin, "pinyin"),
+            Kind::Zhuyin => with_dictionary(candidates::Script::Bopomofo, "zhuyin"),
+            Kind::Transliteration(name) => Composer::Transliteration(
+                transliteration::Composer::new(load_rules(&name))
+            ),
+        }
+    }
+
+    /// Feeds text into the composer.
+    /// Returns the text which got finished as a result.
+    /// On error, the state is left untouched.
+    pub fn push(&mut self, text: &str) -> Result<String, NotComposable> {
+        match self {
+            Composer::Hangul(c) => c.push(text),
+            Composer::Candidates(c) => c.push(text),
+            Composer::Transliteration(c) => c.push(text),
+        }
+    }
+
+    /// Undoes the last press.
+    /// Returns false if there was nothing to undo.
+    pub fn pop(&mut self) -> bool {
+        match self {
+            Composer::Hangul(c) => c.pop(),
+            Composer::Candidates(c) => c.pop(),
+            Composer::Transliteration(c) => c.pop(),
+        }
+    }
+
+    /// Text to display as preedit.
+    pub fn get_preedit(&self) -> String {
+        match self {
+            Composer::Hangul(c) => c.get_preedit(),
+            Composer::Candidates(c) => c.get_preedit(),
+            Composer::Transliteration(c) => c.get_preedit(),
+        }
+    }
+
+    /// Finishes composition, returning the text to commit.
+    pub fn flush(&mut self) -> String {
+        match self {
+            Composer::Hangul(c) => c.flush(),
+            Composer::Candidates(c) => c.flush(),
+            Composer::Transliteration(c) => c.flush(),
+        }
+    }
+
+    /// Returns the candidate to show in the slot, if any.
+    pub fn get_candidate(&self, slot: usize) -> Option<String> {
+        match self {
+            Composer::Hangul(_) | Composer::Transliteration(_) => None,
+            Composer::Candidates(c) => c.get_candidate(slot),
+        }
+    }
+
+    /// Returns the text to commit, if the slot had a candidate.
+    pub fn select_candidate(&mut self, slot: usize) -> Option<String> {
+        match self {
+            Composer::Hangul(_) | Composer::Transliteration(_) => None,
+            Composer::Candidates(c) => c.select_candidate(slot),
+        }
+    }
+
+    pub fn turn_candidate_page(&mut self, forward: bool) {
+        match self {
+            Composer::Hangul(_) | Composer::Transliteration(_) => {},
+            Composer::Candidates(c) => c.turn_page(forward),
+        }
+    }
+}
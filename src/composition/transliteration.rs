@@ -0,0 +1,331 @@
+/*! Rewriting the word being typed according to ordered rules,
+ * as in Vietnamese Telex, or phonetic input of other scripts.
+ *
+ * Rules files consist of lines of the forms:
+ *
+ * - `class <name> <members>...`, where each member is a single character,
+ * - `<pattern> <replacement>`.
+ *
+ * Lines starting with `#` are ignored.
+ *
+ * After each press, the first rule whose pattern matches the end
+ * of the word gets applied. A pattern may refer to classes:
+ * `{name}` matches a single member, `{name}*` matches any number of them.
+ * The references in the replacement take the place of the references
+ * in the pattern, in the same order:
+ * `{name}` is replaced by the member of its class
+ * at the same position as the matched member,
+ * `{name}*` repeats what was matched.
+ *
+ * Any character which is not a letter ends the word.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+
+use super::NotComposable;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Char(char),
+    /// Class name
+    One(String),
+    Many(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    /// For error reporting
+    line: usize,
+    pattern: Vec<Token>,
+    replacement: Vec<Token>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// What a class reference in the pattern matched
+enum Capture {
+    Index(usize),
+    Text(Vec<char>),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Rules {
+    classes: HashMap<String, Vec<char>>,
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    pub fn parse(data: &str) -> Result<Rules, ParseError> {
+        let mut classes = HashMap::new();
+        let mut rules = Vec::new();
+        for (index, line) in data.lines().enumerate() {
+            let error = |message: String| ParseError { line: index + 1, message };
+            if line.starts_with('#') {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {},
+                ["class", name, members @ ..] => {
+                    let members = members.iter()
+                        .map(|m| {
+                            let mut chars = m.chars();
+                            match (chars.next(), chars.next()) {
+                                (Some(c), None) => Ok(c),
+                                _ => Err(error(format!("Class member {} is not a single character", m))),
+                            }
+                        })
+                        .collect::<Result<Vec<char>, ParseError>>()?;
+                    classes.insert(name.to_string(), members);
+                },
+                [pattern, replacement] => {
+                    let rule = Rule {
+                        line: index + 1,
+                        pattern: parse_tokens(pattern).map_err(error)?,
+                        replacement: parse_tokens(replacement).map_err(error)?,
+                    };
+                    rules.push(rule);
+                },
+                _ => return Err(error("Expected a pattern and a replacement".into())),
+            }
+        }
+        let rules = Rules { classes, rules };
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    /// Checks that class references can be resolved.
+    fn validate(&self) -> Result<(), ParseError> {
+        for rule in &self.rules {
+            let error = |message: String| Err(ParseError { line: rule.line, message });
+            let references = |tokens: &Vec<Token>| -> Vec<Token> {
+                tokens.iter()
+                    .filter(|t| !matches!(t, Token::Char(_)))
+                    .cloned()
+                    .collect()
+            };
+            let from = references(&rule.pattern);
+            let to = references(&rule.replacement);
+            if from.len() != to.len() {
+                return error("Pattern and replacement refer to different numbers of classes".into());
+            }
+            for (from, to) in from.iter().zip(to.iter()) {
+                match (from, to) {
+                    (Token::One(a), Token::One(b)) => {
+                        match (self.classes.get(a), self.classes.get(b)) {
+                            (Some(a), Some(b)) if a.len() == b.len() => {},
+                            (Some(_), Some(_)) => return error(format!("Classes {} and {} differ in size", a, b)),
+                            _ => return error(format!("Unknown class in {} or {}", a, b)),
+                        }
+                    },
+                    (Token::Many(a), Token::Many(b)) if a == b => {
+                        if !self.classes.contains_key(a) {
+                            return error(format!("Unknown class {}", a));
+                        }
+                    },
+                    _ => return error("Class references don't correspond".into()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Tries to match the pattern at the end of the word,
+    /// returning the length of the match, and the captures in order.
+    fn match_end(&self, pattern: &[Token], word: &[char]) -> Option<(usize, Vec<Capture>)> {
+        let mut end = word.len();
+        let mut captures = Vec::new();
+        for token in pattern.iter().rev() {
+            match token {
+                Token::Char(c) => {
+                    if end == 0 || word[end - 1] != *c {
+                        return None;
+                    }
+                    end -= 1;
+                },
+                Token::One(name) => {
+                    let class = self.classes.get(name)?;
+                    let index = class.iter()
+                        .position(|m| end > 0 && *m == word[end - 1])?;
+                    captures.push(Capture::Index(index));
+                    end -= 1;
+                },
+                Token::Many(name) => {
+                    let class = self.classes.get(name)?;
+                    let start = word[..end].iter()
+                        .rposition(|c| !class.contains(c))
+                        .map(|i| i + 1)
+                        .unwrap_or(0);
+                    captures.push(Capture::Text(word[start..end].to_vec()));
+                    end = start;
+                },
+            }
+        }
+        captures.reverse();
+        Some((word.len() - end, captures))
+    }
+
+    /// Rewrites the end of the word using the first matching rule.
+    fn apply(&self, word: &mut Vec<char>) {
+        for rule in &self.rules {
+            if let Some((length, captures)) = self.match_end(&rule.pattern, word) {
+                let mut captures = captures.into_iter();
+                let mut replacement = Vec::new();
+                for token in &rule.replacement {
+                    let capture = match token {
+                        Token::Char(_) => None,
+                        _ => captures.next(),
+                    };
+                    match (token, capture) {
+                        (Token::Char(c), _) => replacement.push(*c),
+                        (Token::One(name), Some(Capture::Index(index))) => {
+                            // Validated when parsing
+                            replacement.push(self.classes[name][index]);
+                        },
+                        (Token::Many(_), Some(Capture::Text(text))) => {
+                            replacement.extend(text);
+                        },
+                        // Validated when parsing
+                        _ => {},
+                    }
+                }
+                word.truncate(word.len() - length);
+                word.extend(replacement);
+                return;
+            }
+        }
+    }
+}
+
+fn parse_tokens(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '{' {
+            let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+            if name.is_empty() {
+                return Err(format!("Empty class reference in {}", text));
+            }
+            if chars.peek() == Some(&'*') {
+                chars.next();
+                tokens.push(Token::Many(name));
+            } else {
+                tokens.push(Token::One(name));
+            }
+        } else {
+            tokens.push(Token::Char(c));
+        }
+    }
+    Ok(tokens)
+}
+
+pub struct Composer {
+    rules: Rules,
+    word: Vec<char>,
+}
+
+impl Composer {
+    pub fn new(rules: Rules) -> Composer {
+        Composer {
+            rules,
+            word: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, text: &str) -> Result<String, NotComposable> {
+        if text.is_empty() || !text.chars().all(char::is_alphabetic) {
+            return Err(NotComposable);
+        }
+        for c in text.chars() {
+            self.word.push(c);
+            self.rules.apply(&mut self.word);
+        }
+        Ok(String::new())
+    }
+
+    pub fn pop(&mut self) -> bool {
+        self.word.pop().is_some()
+    }
+
+    pub fn get_preedit(&self) -> String {
+        self.word.iter().collect()
+    }
+
+    pub fn flush(&mut self) -> String {
+        let text = self.get_preedit();
+        self.word.clear();
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn type_text(composer: &mut Composer, text: &str) {
+        for c in text.chars() {
+            composer.push(&c.to_string()).unwrap();
+        }
+    }
+
+    const TONES: &str = "
+class plain a e ê
+class acute á é ế
+class final c t
+ee ê
+{plain}{final}*s {acute}{final}*
+";
+
+    #[test]
+    fn rewrite() {
+        let mut c = Composer::new(Rules::parse(TONES).unwrap());
+        type_text(&mut c, "vieets");
+        assert_eq!(c.get_preedit(), "viết");
+        assert_eq!(c.push(" "), Err(NotComposable));
+        assert_eq!(c.flush(), "viết");
+    }
+
+    #[test]
+    fn no_match() {
+        let mut c = Composer::new(Rules::parse(TONES).unwrap());
+        type_text(&mut c, "sis");
+        assert_eq!(c.get_preedit(), "sis");
+        assert!(c.pop());
+        assert_eq!(c.get_preedit(), "si");
+    }
+
+    #[test]
+    fn bad_rules() {
+        assert!(Rules::parse("{x} y").is_err());
+        assert!(Rules::parse("class a b\nclass c d e\n{a} {c}").is_err());
+        assert!(Rules::parse("a b c").is_err());
+        assert!(Rules::parse("class a bc").is_err());
+    }
+
+    #[test]
+    fn shipped_rules() {
+        let data = crate::resources::get_transliteration("vi+telex").unwrap();
+        let mut c = Composer::new(Rules::parse(data).unwrap());
+        type_text(&mut c, "Tieengs");
+        assert_eq!(c.flush(), "Tiếng");
+        type_text(&mut c, "Vieetj");
+        assert_eq!(c.flush(), "Việt");
+        type_text(&mut c, "ddaay");
+        assert_eq!(c.flush(), "đây");
+        type_text(&mut c, "nguwowif");
+        assert_eq!(c.flush(), "người");
+        type_text(&mut c, "muaf");
+        assert_eq!(c.flush(), "mùa");
+    }
+}
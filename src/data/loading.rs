@@ -5,8 +5,10 @@
 /*! Loading layout files */
 
 use std::env;
+use std::ffi::OsStr;
 use std::fmt;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{ Path, PathBuf };
 
 use super::{ Error, LoadError };
 use super::parsing;
@@ -14,15 +16,22 @@ use super::parsing;
 use crate::layout;
 use crate::layout::ArrangementKind;
 use crate::logging;
+use crate::resources;
 use crate::xdg;
 use crate::imservice::ContentPurpose;
+use serde::Serialize;
 
 
 const FALLBACK_LAYOUT_NAME: &str = "us";
 
+/// Directories for content purposes, which are not overlays.
+/// "terminal" is both, but it's builtin anyway.
+const PURPOSE_DIRECTORIES: &[&str] = &["email", "number", "pin", "url"];
 
-#[derive(Debug, Clone, PartialEq)]
-enum DataSource {
+
+/// Where the layout got loaded from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum DataSource {
     File(PathBuf),
     Resource(String),
 }
@@ -49,15 +58,18 @@ impl fmt::Display for DataSource {
 fn _get_arrangement_names(name: &str, arrangement: ArrangementKind)
     -> Vec<(ArrangementKind, String)>
 {
-    let name_with_arrangement = match arrangement {    
-        ArrangementKind::Base => name.into(),
-        ArrangementKind::Wide => format!("{}_wide", name),
+    let mut ret = match arrangement {
+        ArrangementKind::Base => vec![],
+        ArrangementKind::Wide => vec![
+            (ArrangementKind::Wide, format!("{}_wide", name)),
+        ],
+        // Wide layouts get split when loaded,
+        // see `load_layout_with_source`.
+        ArrangementKind::Split => vec![
+            (ArrangementKind::Split, format!("{}_split", name)),
+            (ArrangementKind::Wide, format!("{}_wide", name)),
+        ],
     };
-    
-    let mut ret = Vec::new();
-    if name_with_arrangement != name {
-        ret.push((arrangement, name_with_arrangement));
-    }
     ret.push((ArrangementKind::Base, name.into()));
     ret
 }
@@ -191,25 +203,148 @@ fn iter_layout_sources(
     to_layout_sources(paths, layout_storage)
 }
 
-fn load_layout_data(source: DataSource)
+/// The `notice`, if any, is shown on top of the layout.
+fn load_layout_data(source: DataSource, notice: Option<&str>)
     -> Result<crate::layout::LayoutParseData, LoadError>
 {
     let handler = logging::Print {};
-    match source {
+    let layout = match source {
         DataSource::File(path) => {
             parsing::Layout::from_file(path.clone())
                 .map_err(LoadError::BadData)
-                .and_then(|layout|
-                    layout.build(handler).0.map_err(LoadError::BadKeyMap)
-                )
         },
         DataSource::Resource(name) => {
             parsing::Layout::from_resource(&name)
-                .and_then(|layout|
-                    layout.build(handler).0.map_err(LoadError::BadKeyMap)
-                )
         },
-    }
+    }?;
+    let layout = match notice {
+        Some(text) => layout.with_notice(text),
+        None => layout,
+    };
+    layout.build(handler).0.map_err(LoadError::BadKeyMap)
+}
+
+/// Tells the user that their own layout didn't make it,
+/// without covering too much of the keyboard.
+fn get_rejection_notice(file: &Path) -> String {
+    format!(
+        "Layout {} has errors, see log",
+        file.file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_else(|| file.to_string_lossy()),
+    )
+}
+
+/// Where the user's own layouts are
+fn get_storage_path() -> Option<PathBuf> {
+    env::var_os("SQUEEKBOARD_KEYBOARDSDIR")
+        .map(PathBuf::from)
+        .or_else(|| xdg::data_path("squeekboard/keyboards"))
+}
+
+/// Returns the metadata of the first layout found under the name,
+/// without falling back to other names.
+fn find_meta(name: &str, overlay: Option<&str>) -> Option<parsing::Meta> {
+    let paths = to_layout_paths(
+        vec![(ArrangementKind::Base, name.into())],
+        ContentPurpose::Normal,
+        overlay,
+    );
+    to_layout_sources(paths, get_storage_path())
+        .find_map(|(_kind, source)| {
+            let layout = match &source {
+                DataSource::File(path) => parsing::Layout::from_file(path.clone())
+                    .map_err(LoadError::BadData),
+                DataSource::Resource(name) => parsing::Layout::from_resource(name),
+            };
+            match layout {
+                Ok(layout) => Some(layout.get_meta().cloned()),
+                Err(LoadError::BadData(Error::Missing(_)))
+                | Err(LoadError::MissingResource) => None,
+                Err(e) => {
+                    log_print!(
+                        logging::Level::Debug,
+                        "No metadata from {}: {}",
+                        source, e,
+                    );
+                    None
+                },
+            }
+        })
+        .flatten()
+}
+
+/// Metadata of the layout with the name, as found in system settings
+pub fn get_meta(name: &str) -> Option<parsing::Meta> {
+    find_meta(name, None)
+}
+
+/// Metadata of the overlay, taken from its fallback layout,
+/// or from any of the user's layouts in it
+pub fn get_overlay_meta(overlay: &str) -> Option<parsing::Meta> {
+    find_meta(FALLBACK_LAYOUT_NAME, Some(overlay))
+        .or_else(|| {
+            let directory = get_storage_path()?.join(overlay);
+            get_layout_names(&directory).iter()
+                .find_map(|name| find_meta(name, Some(overlay)))
+        })
+}
+
+/// Names of layout files in the directory, sorted
+fn get_layout_names(directory: &Path) -> Vec<String> {
+    let mut names: Vec<String> = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.extension() == Some(OsStr::new("yaml")))
+            .filter_map(|path| {
+                path.file_stem()
+                    .and_then(OsStr::to_str)
+                    .map(String::from)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+    names
+}
+
+/// Each subdirectory with layouts is an overlay,
+/// unless it's meant for a content purpose, or builtin.
+fn find_custom_overlays(storage: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(storage) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log_print!(
+                logging::Level::Debug,
+                "Can't look for overlays in {:?}: {}",
+                storage, e,
+            );
+            return Vec::new();
+        },
+    };
+    let builtin = resources::get_overlays();
+    let mut overlays: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry.file_type().map(|t| t.is_dir()).unwrap_or(false)
+        })
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| {
+            !PURPOSE_DIRECTORIES.contains(&name.as_str())
+                && !builtin.contains(&name.as_str())
+        })
+        .filter(|name| !get_layout_names(&storage.join(name)).is_empty())
+        .collect();
+    overlays.sort();
+    overlays
+}
+
+/// Overlays which the user added to the layout directory
+pub fn get_custom_overlays() -> Vec<String> {
+    get_storage_path()
+        .map(|storage| find_custom_overlays(&storage))
+        .unwrap_or_default()
 }
 
 fn load_layout_data_with_fallback(
@@ -217,15 +352,25 @@ fn load_layout_data_with_fallback(
     kind: ArrangementKind,
     purpose: ContentPurpose,
     overlay: Option<&str>,
-) -> (ArrangementKind, layout::LayoutParseData) {
+) -> (ArrangementKind, layout::LayoutParseData, DataSource) {
 
-    // Build the path to the right keyboard layout subdirectory
-    let path = env::var_os("SQUEEKBOARD_KEYBOARDSDIR")
-        .map(PathBuf::from)
-        .or_else(|| xdg::data_path("squeekboard/keyboards"));
+    let path = get_storage_path();
+
+    // The first user-provided file which failed
+    let mut rejected = None;
 
-    for (kind, source) in iter_layout_sources(&name, kind, purpose, overlay, path) {
-        let layout = load_layout_data(source.clone());
+    // Custom overlays don't necessarily cover all the layouts,
+    // so those without one get the layout without the overlay.
+    let without_overlay = overlay
+        .map(|_| iter_layout_sources(&name, kind, purpose, None, path.clone()))
+        .into_iter()
+        .flatten();
+    let sources = iter_layout_sources(&name, kind, purpose, overlay, path)
+        .chain(without_overlay);
+
+    for (kind, source) in sources {
+        let notice = rejected.as_deref().map(get_rejection_notice);
+        let layout = load_layout_data(source.clone(), notice.as_deref());
         match layout {
             Err(e) => match (e, source) {
                 (
@@ -236,15 +381,20 @@ fn load_layout_data_with_fallback(
                     "Tried file {:?}, but it's missing: {}",
                     file, e
                 ),
-                (e, source) => log_print!(
-                    logging::Level::Warning,
-                    "Failed to load layout from {}: {}, skipping",
-                    source, e
-                ),
+                (e, source) => {
+                    log_print!(
+                        logging::Level::Warning,
+                        "Failed to load layout from {}: {}, skipping",
+                        source, e
+                    );
+                    if let DataSource::File(file) = source {
+                        rejected = rejected.or(Some(file));
+                    }
+                },
             },
             Ok(layout) => {
                 log_print!(logging::Level::Info, "Loaded layout {}", source);
-                return (kind, layout);
+                return (kind, layout, source);
             }
         }
     }
@@ -258,10 +408,26 @@ pub fn load_layout(
     variant: ContentPurpose,
     overlay: &Option<String>,
 ) -> layout::Layout {
+    load_layout_with_source(name, kind, variant, overlay).0
+}
+
+/// Like `load_layout`, but also tells where the layout came from.
+pub fn load_layout_with_source(
+    name: &String,
+    kind: ArrangementKind,
+    variant: ContentPurpose,
+    overlay: &Option<String>,
+) -> (layout::Layout, DataSource) {
     let overlay = overlay.as_ref().map(String::as_str);
-    let (found_kind, layout)
+    let (found_kind, layout, source)
         = load_layout_data_with_fallback(name, kind, variant, overlay);
-    layout::Layout::new(layout, found_kind, variant)
+    // Dedicated split layouts already have their gap.
+    let (found_kind, layout) = match (kind, found_kind) {
+        (ArrangementKind::Split, ArrangementKind::Wide)
+            => (ArrangementKind::Split, layout.split()),
+        _ => (found_kind, layout),
+    };
+    (layout::Layout::new(layout, found_kind, variant), source)
 }
 
 #[cfg(test)]
@@ -270,6 +436,25 @@ mod tests {
 
     use crate::logging::ProblemPanic;
 
+    #[test]
+    fn custom_overlays() {
+        let storage = env::temp_dir()
+            .join(format!("squeekboard-overlays-{}", std::process::id()));
+        for (directory, file) in [
+            ("math", "us.yaml"),
+            ("gaming", "de.yaml"),
+            ("empty", "README"),
+            ("email", "us.yaml"),
+            ("terminal", "us.yaml"),
+        ] {
+            fs::create_dir_all(storage.join(directory)).unwrap();
+            fs::write(storage.join(directory).join(file), "").unwrap();
+        }
+        let overlays = find_custom_overlays(&storage);
+        fs::remove_dir_all(&storage).unwrap();
+        assert_eq!(overlays, vec!["gaming", "math"]);
+    }
+
     #[test]
     fn parsing_fallback() {
         assert!(parsing::Layout::from_resource(FALLBACK_LAYOUT_NAME)
@@ -356,6 +541,33 @@ mod tests {
         );
     }
 
+    /// Split layouts fall back to splitting wide ones.
+    #[test]
+    fn test_preferences_order_split() {
+        let sources = iter_layout_sources("nb", ArrangementKind::Split, ContentPurpose::Normal, None, None);
+
+        assert_eq!(
+            sources.collect::<Vec<_>>(),
+            vec!(
+                (ArrangementKind::Split, DataSource::Resource("nb_split".into())),
+                (ArrangementKind::Wide, DataSource::Resource("nb_wide".into())),
+                (ArrangementKind::Base, DataSource::Resource("nb".into())),
+                (
+                    ArrangementKind::Split,
+                    DataSource::Resource("us_split".into())
+                ),
+                (
+                    ArrangementKind::Wide,
+                    DataSource::Resource("us_wide".into())
+                ),
+                (
+                    ArrangementKind::Base,
+                    DataSource::Resource("us".into())
+                ),
+            )
+        );
+    }
+
     #[test]
     fn test_preferences_order_overlay() {
         let sources = iter_layout_sources("nb", ArrangementKind::Base, ContentPurpose::Normal, Some("terminal"), None);
@@ -6,7 +6,9 @@
 
 pub mod loading;
 pub mod parsing;
+mod schema;
 
+use std::cmp;
 use std::io;
 use std::fmt;
 
@@ -15,7 +17,7 @@ use crate::keyboard::FormattingError;
 /// Errors encountered loading the layout into yaml
 #[derive(Debug)]
 pub enum Error {
-    Yaml(serde_yaml::Error),
+    Yaml(YamlError),
     Io(io::Error),
     /// The file was missing.
     /// It's distinct from Io in order to make it matchable
@@ -33,6 +35,112 @@ impl fmt::Display for Error {
     }
 }
 
+/// A YAML error, pointing at the place in the text where it happened
+#[derive(Debug)]
+pub struct YamlError {
+    error: serde_yaml::Error,
+    /// Caret-style, ready to print
+    snippet: Option<String>,
+    /// Suggestion for the unknown name, if any
+    hint: Option<String>,
+}
+
+impl YamlError {
+    /// `text` is what was parsed.
+    pub fn new(error: serde_yaml::Error, text: &str) -> Self {
+        let snippet = error.location()
+            .and_then(|location| get_snippet(
+                text,
+                location.line(),
+                location.column(),
+                1,
+            ));
+        let hint = get_hint(&error.to_string());
+        YamlError { error, snippet, hint }
+    }
+
+    pub fn location(&self) -> Option<serde_yaml::Location> {
+        self.error.location()
+    }
+}
+
+impl fmt::Display for YamlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n{}", snippet)?;
+        }
+        if let Some(hint) = &self.hint {
+            write!(f, "\n{}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows the line with carets under the given span.
+/// Line and column count from 1, like in serde_yaml.
+fn get_snippet(text: &str, line: usize, column: usize, width: usize)
+    -> Option<String>
+{
+    let source_line = text.lines().nth(line.checked_sub(1)?)?;
+    // Tabs are kept, so that the carets line up with the text.
+    let indent: String = source_line.chars()
+        .take(column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let number = line.to_string();
+    let gutter = " ".repeat(number.len());
+    Some(format!(
+        "{} |\n{} | {}\n{} | {}{}",
+        gutter,
+        number, source_line,
+        gutter, indent, "^".repeat(cmp::max(width, 1)),
+    ))
+}
+
+/// Picks the closest name from serde's complaint like
+/// "unknown field `x`, expected one of `a`, `b`".
+fn get_hint(message: &str) -> Option<String> {
+    let start = message.find("unknown field `")
+        .or_else(|| message.find("unknown variant `"))?;
+    // Names are the odd pieces between backticks
+    let mut names = message[start..].split('`').skip(1).step_by(2);
+    let unknown = names.next()?;
+    // Like rustc, allow about one mistake per 3 letters,
+    // or any amount of swapped letters
+    let max_distance = cmp::max(unknown.chars().count(), 3) / 3;
+    let sorted = |name: &str| {
+        let mut chars: Vec<char> = name.chars().collect();
+        chars.sort_unstable();
+        chars
+    };
+    names
+        .map(|name| (get_edit_distance(unknown, name), name))
+        .filter(|(distance, name)| {
+            *distance <= max_distance || sorted(name) == sorted(unknown)
+        })
+        .min_by_key(|(distance, _name)| *distance)
+        .map(|(_distance, name)| format!("Did you mean `{}`?", name))
+}
+
+/// Levenshtein distance
+fn get_edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(cmp::min(
+                substitution,
+                cmp::min(previous[j + 1], current[j]) + 1,
+            ));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
         let kind = e.kind();
@@ -48,7 +156,7 @@ impl From<io::Error> for Error {
 pub enum LoadError {
     BadData(Error),
     MissingResource,
-    BadResource(serde_yaml::Error),
+    BadResource(YamlError),
     BadKeyMap(FormattingError),
 }
 
@@ -63,3 +171,32 @@ impl fmt::Display for LoadError {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn snippet_caret() {
+        assert_eq!(
+            get_snippet("views:\n    bse:\n", 2, 5, 3).unwrap(),
+            "  |\n2 |     bse:\n  |     ^^^",
+        );
+    }
+
+    #[test]
+    fn hint_closest() {
+        assert_eq!(
+            get_hint("buttons.a: unknown field `labl`, expected one of `label`, `icon`, `outline` at line 3 column 9"),
+            Some("Did you mean `label`?".into()),
+        );
+        assert_eq!(
+            get_hint("unknown field `lable`, expected `label` or `outline`"),
+            Some("Did you mean `label`?".into()),
+        );
+        assert_eq!(
+            get_hint("unknown field `bad_field`, expected one of `margins`, `views`"),
+            None,
+        );
+    }
+}
@@ -12,9 +12,14 @@ use std::vec::Vec;
 
 use xkbcommon::xkb;
 
-use super::{ Error, LoadError };
+use super::{ Error, LoadError, YamlError };
+use super::schema::{
+    Action, ButtonMeta, Composition, Modifier, Outline,
+};
+pub use super::schema::{ Layout, Meta };
 
 use crate::action;
+use crate::composition;
 use crate::keyboard::{
     Key, generate_keymaps, generate_keycodes, KeyCode, FormattingError
 };
@@ -23,103 +28,111 @@ use crate::logging;
 use crate::resources;
 
 // traits, derives
-use serde::Deserialize;
-use std::io::BufReader;
 use std::iter::FromIterator;
 use crate::logging::Warn;
 
 // TODO: find a nice way to make sure non-positive sizes don't break layouts
 
-/// The root element describing an entire keyboard
-#[derive(Debug, Deserialize, PartialEq)]
-#[serde(deny_unknown_fields)]
-pub struct Layout {
-    #[serde(default)]
-    margins: Margins,
-    views: HashMap<String, Vec<ButtonIds>>,
-    #[serde(default)] 
-    buttons: HashMap<String, ButtonMeta>,
-    outlines: HashMap<String, Outline>
-}
+/// Names the button and the outline added by `Layout::with_notice`
+const NOTICE_NAME: &str = "squeekboard_notice";
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Default)]
-#[serde(deny_unknown_fields)]
-struct Margins {
-    top: f64,
-    bottom: f64,
-    side: f64,
-}
+/// The text the layout was read from, for pointing at problems
+struct Source(String);
 
-/// Buttons are embedded in a single string
-type ButtonIds = String;
-
-/// All info about a single button
-/// Buttons can have multiple instances though.
-#[derive(Debug, Default, Deserialize, PartialEq)]
-#[serde(deny_unknown_fields)]
-struct ButtonMeta {
-    // TODO: structure (action, keysym, text, modifier) as an enum
-    // to detect conflicts and missing values at compile time
-    /// Special action to perform on activation.
-    /// Conflicts with keysym, text, modifier.
-#[serde(with = "serde_yaml::with::singleton_map", default)]
-    action: Option<Action>,
-    /// The name of the XKB keysym to emit on activation.
-    /// Conflicts with action, text, modifier.
-    keysym: Option<String>,
-    /// The text to submit on activation. Will be derived from ID if not present
-    /// Conflicts with action, keysym, modifier.
-    text: Option<String>,
-    /// The modifier to apply while the key is locked
-    /// Conflicts with action, keysym, text
-    modifier: Option<Modifier>,
-    /// If not present, will be derived from text or the button ID
-    label: Option<String>,
-    /// Conflicts with label
-    icon: Option<String>,
-    /// The name of the outline. If not present, will be "default"
-    outline: Option<String>,
-}
+impl Source {
+    /// Returns the line and column where the button is defined,
+    /// or failing that, where it's placed in a view.
+    /// The deserializer doesn't keep the positions of buttons,
+    /// so they get searched for in the text.
+    /// When the name is found in more than one place,
+    /// there's no telling which one is meant, so nothing is returned.
+    /// For example, a button named like a property
+    /// can't be told apart from that property in other buttons.
+    fn find_button(&self, name: &str) -> Option<(usize, usize)> {
+        let text = &self.0;
+        let lines: Vec<&str> = text.lines().collect();
+        let get_column = |line: &str, index: usize| line[..index].chars().count() + 1;
+        let get_only = |found: Vec<(usize, usize)>| match found.as_slice() {
+            [place] => Some(*place),
+            _ => None,
+        };
 
-#[derive(Debug, Deserialize, PartialEq, Clone)]
-#[serde(deny_unknown_fields)]
-enum Action {
-    #[serde(rename="locking")]
-    Locking {
-        lock_view: String,
-        unlock_view: String,
-        pops: Option<bool>,
-        #[serde(default)]
-        looks_locked_from: Vec<String>,
-    },
-    #[serde(rename="set_view")]
-    SetView(String),
-    #[serde(rename="show_prefs")]
-    ShowPrefs,
-    /// Remove last character
-    #[serde(rename="erase")]
-    Erase,
+        let buttons_start = lines.iter()
+            .position(|line| line.trim_end() == "buttons:");
+        let definitions: Vec<_> = match buttons_start {
+            Some(start) => lines[start + 1..].iter()
+                .enumerate()
+                // The section ends on the next top level key.
+                .take_while(|(_i, line)| {
+                    line.trim().is_empty() || line.starts_with(char::is_whitespace)
+                })
+                .filter_map(|(i, line)| {
+                    let key = line.trim_start();
+                    let is_definition = [
+                        format!("{}:", name),
+                        format!("\"{}\":", name),
+                        format!("'{}':", name),
+                    ].iter().any(|definition| key.starts_with(definition.as_str()));
+                    if is_definition {
+                        let indent = line.len() - key.len();
+                        Some((start + 1 + i + 1, get_column(line, indent)))
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        if !definitions.is_empty() {
+            return get_only(definitions);
+        }
+
+        let is_separator = |c: Option<char>| match c {
+            None | Some('"') | Some('\'') => true,
+            Some(c) => c.is_whitespace(),
+        };
+        let uses = lines.iter()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                line.match_indices(name)
+                    .filter(move |(index, _)| {
+                        is_separator(line[..*index].chars().next_back())
+                            && is_separator(line[index + name.len()..].chars().next())
+                    })
+                    .map(move |(index, _)| (i + 1, get_column(line, index)))
+            })
+            .collect();
+        get_only(uses)
+    }
+
+    fn get_button_snippet(&self, name: &str) -> Option<String> {
+        let (line, column) = self.find_button(name)?;
+        super::get_snippet(
+            &self.0,
+            line,
+            column,
+            name.chars().count(),
+        )
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
-#[serde(deny_unknown_fields)]
-enum Modifier {
-    Control,
-    Shift,
-    Lock,
-    #[serde(alias="Mod1")]
-    Alt,
-    Mod2,
-    Mod3,
-    Mod4,
-    Mod5,
+/// Points messages about a button at its place in the text.
+struct Located<'a, H: logging::Handler> {
+    handler: &'a mut H,
+    source: &'a Source,
+    button: &'a str,
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq)]
-#[serde(deny_unknown_fields)]
-struct Outline {
-    width: f64,
-    height: f64,
+impl<'a, H: logging::Handler> logging::Handler for Located<'a, H> {
+    fn handle(&mut self, level: logging::Level, message: &str) {
+        match self.source.get_button_snippet(self.button) {
+            Some(snippet) => self.handler.handle(
+                level,
+                &format!("{}\n{}", message, snippet),
+            ),
+            None => self.handler.handle(level, message),
+        }
+    }
 }
 
 pub fn add_offsets<'a, I: 'a, T, F: 'a>(iterator: I, get_size: F)
@@ -136,24 +149,103 @@ pub fn add_offsets<'a, I: 'a, T, F: 'a>(iterator: I, get_size: F)
     })
 }
 
+/// A layout together with the text it was read from
+pub struct Parsed {
+    pub layout: Layout,
+    source: Source,
+}
+
+impl Parsed {
+    /// See `Layout::with_notice`.
+    pub fn with_notice(self, text: &str) -> Parsed {
+        Parsed {
+            layout: self.layout.with_notice(text),
+            source: self.source,
+        }
+    }
+
+    pub fn get_meta(&self) -> Option<&Meta> {
+        self.layout.get_meta()
+    }
+
+    /// Warnings about buttons point at their place in the text.
+    pub fn build<H: logging::Handler>(self, warning_handler: H)
+        -> (Result<crate::layout::LayoutParseData, FormattingError>, H)
+    {
+        self.layout.build(&self.source, warning_handler)
+    }
+}
+
 impl Layout {
-    pub fn from_resource(name: &str) -> Result<Layout, LoadError> {
+    pub fn from_resource(name: &str) -> Result<Parsed, LoadError> {
         let data = resources::get_keyboard(name)
                     .ok_or(LoadError::MissingResource)?;
-        serde_yaml::from_str(data)
-                    .map_err(LoadError::BadResource)
+        Self::from_text(data)
+            .map_err(LoadError::BadResource)
+    }
+
+    pub fn from_file(path: PathBuf) -> Result<Parsed, Error> {
+        let text = fs::read_to_string(&path)?;
+        Self::from_text(&text).map_err(Error::Yaml)
+    }
+
+    fn from_text(text: &str) -> Result<Parsed, YamlError> {
+        serde_yaml::from_str(text)
+            .map(|layout| Parsed {
+                layout,
+                source: Source(text.into()),
+            })
+            .map_err(|e| YamlError::new(e, text))
     }
 
-    pub fn from_file(path: PathBuf) -> Result<Layout, Error> {
-        let infile = BufReader::new(
-            fs::OpenOptions::new()
-                .read(true)
-                .open(&path)?
+    pub fn get_meta(&self) -> Option<&Meta> {
+        self.meta.as_ref()
+    }
+
+    /// Adds a row on top of every view, with a key showing the text.
+    /// The key does nothing.
+    pub fn with_notice(mut self, text: &str) -> Layout {
+        let width = self.views.values()
+            .flat_map(|rows| rows.iter())
+            .map(|row| self.get_row_width(row))
+            .fold(0.0, f64::max);
+        let height = self.outlines.get("default")
+            .map(|outline| outline.height)
+            .unwrap_or(1.0);
+        self.outlines.insert(
+            NOTICE_NAME.into(),
+            Outline { width, height },
+        );
+        self.buttons.insert(
+            NOTICE_NAME.into(),
+            ButtonMeta {
+                action: Some(Action::Nothing),
+                label: Some(text.into()),
+                outline: Some(NOTICE_NAME.into()),
+                ..Default::default()
+            },
         );
-        serde_yaml::from_reader(infile).map_err(Error::Yaml)
+        for rows in self.views.values_mut() {
+            rows.insert(0, NOTICE_NAME.into());
+        }
+        self
+    }
+
+    fn get_row_width(&self, row: &str) -> f64 {
+        row.split_ascii_whitespace()
+            .map(|name| {
+                let outline = self.buttons.get(name)
+                    .and_then(|meta| meta.outline.as_ref())
+                    .map(String::as_str)
+                    .unwrap_or("default");
+                self.outlines.get(outline)
+                    .map(|outline| outline.width)
+                    .unwrap_or(1.0)
+            })
+            .sum()
     }
 
-    pub fn build<H: logging::Handler>(self, mut warning_handler: H)
+    fn build<H: logging::Handler>(self, source: &Source, mut warning_handler: H)
         -> (Result<crate::layout::LayoutParseData, FormattingError>, H)
     {
         let button_names = self.views.values()
@@ -172,7 +264,11 @@ impl Layout {
                     &self.buttons,
                     name,
                     self.views.keys().collect(),
-                    &mut warning_handler,
+                    &mut Located {
+                        handler: &mut warning_handler,
+                        source,
+                        button: name,
+                    },
                 )
             )}).collect();
 
@@ -232,7 +328,11 @@ impl Layout {
                                 button_states_cache.get(name.into())
                                     .expect("Button state not created")
                                     .clone(),
-                                &mut warning_handler,
+                                &mut Located {
+                                    handler: &mut warning_handler,
+                                    source,
+                                    button: name,
+                                },
                             )
                         });
                     layout::Row::new(
@@ -282,6 +382,13 @@ impl Layout {
                     bottom: self.margins.bottom,
                     right: self.margins.side,
                 },
+                composition: self.composition.map(|c| match c {
+                    Composition::Hangul => composition::Kind::Hangul,
+                    Composition::Pinyin => composition::Kind::Pinyin,
+                    Composition::Zhuyin => composition::Kind::Zhuyin,
+                    Composition::Transliteration(name)
+                        => composition::Kind::Transliteration(name),
+                }),
             }),
             warning_handler,
         )
@@ -387,6 +494,47 @@ fn create_action<H: logging::Handler>(
             Action::ShowPrefs
         ) => crate::action::Action::ShowPreferences,
         SubmitData::Action(Action::Erase) => action::Action::Erase,
+        SubmitData::Action(
+            Action::UnicodeInput(view_name)
+        ) => action::Action::UnicodeInput(
+            filter_view_name(
+                name, view_name.clone(), &view_names,
+                warning_handler,
+            )
+        ),
+        SubmitData::Action(Action::HexDigit(digit)) => {
+            let mut chars = digit.chars();
+            match (chars.next().and_then(|c| c.to_digit(16)), chars.next()) {
+                (Some(value), None) => action::Action::HexDigit(value as u8),
+                _ => {
+                    warning_handler.handle(
+                        logging::Level::Warning,
+                        &format!(
+                            "Button {} has an invalid hex digit: {}",
+                            name, digit,
+                        ),
+                    );
+                    action::Action::Submit {
+                        text: None,
+                        keys: Vec::new(),
+                    }
+                },
+            }
+        },
+        SubmitData::Action(Action::HexErase) => action::Action::HexErase,
+        SubmitData::Action(
+            Action::CommitUnicode
+        ) => action::Action::CommitUnicode,
+        SubmitData::Action(
+            Action::Candidate(slot)
+        ) => action::Action::Candidate(slot),
+        SubmitData::Action(
+            Action::PreviousCandidates
+        ) => action::Action::PreviousCandidates,
+        SubmitData::Action(
+            Action::NextCandidates
+        ) => action::Action::NextCandidates,
+        SubmitData::Action(Action::Nothing) => action::Action::Nothing,
         SubmitData::Keysym(keysym) => crate::action::Action::Submit {
             text: None,
             keys: vec!(crate::action::KeySym(
@@ -544,6 +692,8 @@ mod tests {
     use super::*;
     
     use std::env;
+
+    use crate::data::schema::Margins;
     
     use crate::logging::ProblemPanic;
 
@@ -567,8 +717,9 @@ mod tests {
     #[test]
     fn test_parse_path() {
         assert_eq!(
-            Layout::from_file(path_from_root("tests/layout.yaml")).unwrap(),
+            Layout::from_file(path_from_root("tests/layout.yaml")).unwrap().layout,
             Layout {
+                meta: None,
                 margins: Margins { top: 0f64, bottom: 0f64, side: 0f64 },
                 views: hashmap!(
                     "base".into() => vec!("test".into()),
@@ -587,6 +738,7 @@ mod tests {
                 outlines: hashmap!{
                     "default".into() => Outline { width: 0f64, height: 0f64 }, 
                 },
+                composition: None,
             }
         );
     }
@@ -630,6 +782,87 @@ mod tests {
         }
     }
     
+    /// Collects the messages
+    struct Messages(Vec<String>);
+
+    impl logging::Handler for Messages {
+        fn handle(&mut self, _level: logging::Level, message: &str) {
+            self.0.push(message.into());
+        }
+    }
+
+    #[test]
+    fn warning_location() {
+        let layout = Layout::from_text(
+r#"views:
+    base:
+        - "a bad"
+buttons:
+    bad:
+        keysym: "nonsense"
+outlines:
+    default: { width: 1, height: 1 }
+"#
+        ).unwrap();
+        let (layout, messages) = layout.build(Messages(Vec::new()));
+        layout.unwrap();
+        assert_eq!(
+            messages.0,
+            vec!["Keysym name invalid: nonsense\n  |\n5 |     bad:\n  |     ^^^"],
+        );
+    }
+
+    /// Names found in more than one place don't get pointed at.
+    #[test]
+    fn ambiguous_location() {
+        let source = Source(
+r#"views:
+    base:
+        - "a b"
+buttons:
+    a:
+        label: "b"
+outlines:
+    default: { width: 1, height: 1 }
+"#.into()
+        );
+        assert_eq!(source.find_button("a"), Some((5, 5)));
+        assert_eq!(source.find_button("b"), None);
+    }
+
+    #[test]
+    fn meta_name() {
+        let meta = Meta {
+            name: Some("Portuguese".into()),
+            translated_names: hashmap!{
+                "pt".into() => "Português".into(),
+                "pt_BR".into() => "Português (Brasil)".into(),
+            },
+            ..Default::default()
+        };
+        assert_eq!(meta.get_name(&["pt_BR.UTF-8".into()]), Some("Português (Brasil)"));
+        assert_eq!(meta.get_name(&["pt_PT.UTF-8".into()]), Some("Português"));
+        assert_eq!(meta.get_name(&["de_DE".into(), "pt".into()]), Some("Português"));
+        assert_eq!(meta.get_name(&["C".into()]), Some("Portuguese"));
+    }
+
+    #[test]
+    fn notice_on_top() {
+        let out = Layout::from_file(path_from_root("tests/layout.yaml"))
+            .unwrap()
+            .with_notice("Rejected")
+            .build(ProblemPanic).0
+            .unwrap();
+        let rows = out.views["base"].1.get_rows();
+        assert_eq!(rows.len(), 2);
+        let notice = &rows[0].1.get_buttons()[0].1;
+        assert_eq!(
+            notice.label,
+            crate::layout::Label::Text(CString::new("Rejected").unwrap()),
+        );
+        assert_eq!(notice.action, action::Action::Nothing);
+    }
+
     #[test]
     fn test_layout_punctuation() {
         let out = Layout::from_file(path_from_root("tests/layout_key1.yaml"))
@@ -692,6 +925,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_layout_unicode() {
+        let out = Layout::from_file(path_from_root("tests/layout_unicode.yaml"))
+            .unwrap()
+            .build(ProblemPanic).0
+            .unwrap();
+        let actions: Vec<_> = out.views["hex"].1
+            .get_rows()[0].1
+            .get_buttons().iter()
+            .map(|(_offset, button)| button.action.clone())
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                action::Action::HexDigit(0xa),
+                action::Action::HexDigit(0),
+                action::Action::HexErase,
+                action::Action::CommitUnicode,
+                action::Action::UnicodeInput("hex".into()),
+            ],
+        );
+    }
+
     #[test]
     fn unicode_keysym() {
         let keysym = xkb::keysym_from_name(
@@ -0,0 +1,187 @@
+/* Copyright (C) 2020-2021 Purism SPC
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/*! The structure of layout files, as deserialized.
+ *
+ * Uses nothing from the rest of the crate,
+ * because the build script checks the builtin layouts with it.
+ */
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+
+/// The root element describing an entire keyboard
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    /// Describes the layout to people choosing it
+    #[serde(default)]
+    pub meta: Option<Meta>,
+    #[serde(default)]
+    pub margins: Margins,
+    pub views: HashMap<String, Vec<ButtonIds>>,
+    #[serde(default)] 
+    pub buttons: HashMap<String, ButtonMeta>,
+    pub outlines: HashMap<String, Outline>,
+    /// Composes multiple presses into characters, shown as preedit
+    #[serde(with = "serde_yaml::with::singleton_map", default)]
+    pub composition: Option<Composition>,
+}
+
+/// Information about the layout as a whole.
+/// All of it is optional.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Meta {
+    /// Human-readable, shown in the layout chooser
+    pub name: Option<String>,
+    /// The name in other languages, keyed by locale, like "de" or "pt_BR"
+    #[serde(default)]
+    pub translated_names: HashMap<String, String>,
+    /// ISO 639 code of the language typed with the layout
+    pub language: Option<String>,
+    /// ISO 15924 code of the script, like "Latn"
+    pub script: Option<String>,
+    pub author: Option<String>,
+    /// Extra words to find the layout by
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+impl Meta {
+    /// Picks the name in the first locale which has a translation,
+    /// falling back to the untranslated one.
+    /// Locales look like "pt_BR.UTF-8",
+    /// and the translation for "pt" is good enough for "pt_BR".
+    pub fn get_name(&self, locales: &[String]) -> Option<&str> {
+        locales.iter()
+            .flat_map(|locale| {
+                let locale = locale.split(['.', '@'])
+                    .next()
+                    .unwrap_or(locale);
+                let language = locale.split('_').next().unwrap_or(locale);
+                vec![locale, language]
+            })
+            .find_map(|locale| self.translated_names.get(locale))
+            .or(self.name.as_ref())
+            .map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub enum Composition {
+    /// Hangul syllables out of jamo
+    #[serde(rename="hangul")]
+    Hangul,
+    #[serde(rename="pinyin")]
+    Pinyin,
+    #[serde(rename="zhuyin")]
+    Zhuyin,
+    /// Rewrites words according to the named rules file
+    #[serde(rename="transliteration")]
+    Transliteration(String),
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Margins {
+    pub top: f64,
+    pub bottom: f64,
+    pub side: f64,
+}
+
+/// Buttons are embedded in a single string
+pub type ButtonIds = String;
+
+/// All info about a single button
+/// Buttons can have multiple instances though.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ButtonMeta {
+    // TODO: structure (action, keysym, text, modifier) as an enum
+    // to detect conflicts and missing values at compile time
+    /// Special action to perform on activation.
+    /// Conflicts with keysym, text, modifier.
+#[serde(with = "serde_yaml::with::singleton_map", default)]
+    pub action: Option<Action>,
+    /// The name of the XKB keysym to emit on activation.
+    /// Conflicts with action, text, modifier.
+    pub keysym: Option<String>,
+    /// The text to submit on activation. Will be derived from ID if not present
+    /// Conflicts with action, keysym, modifier.
+    pub text: Option<String>,
+    /// The modifier to apply while the key is locked
+    /// Conflicts with action, keysym, text
+    pub modifier: Option<Modifier>,
+    /// If not present, will be derived from text or the button ID
+    pub label: Option<String>,
+    /// Conflicts with label
+    pub icon: Option<String>,
+    /// The name of the outline. If not present, will be "default"
+    pub outline: Option<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub enum Action {
+    #[serde(rename="locking")]
+    Locking {
+        lock_view: String,
+        unlock_view: String,
+        pops: Option<bool>,
+        #[serde(default)]
+        looks_locked_from: Vec<String>,
+    },
+    #[serde(rename="set_view")]
+    SetView(String),
+    #[serde(rename="show_prefs")]
+    ShowPrefs,
+    /// Remove last character
+    #[serde(rename="erase")]
+    Erase,
+    /// Enter a character by its code point, using the given view
+    #[serde(rename="unicode_input")]
+    UnicodeInput(String),
+    #[serde(rename="hex_digit")]
+    HexDigit(String),
+    #[serde(rename="hex_erase")]
+    HexErase,
+    #[serde(rename="commit_unicode")]
+    CommitUnicode,
+    /// Slot for a composition candidate, counted from 0
+    #[serde(rename="candidate")]
+    Candidate(usize),
+    #[serde(rename="previous_candidates")]
+    PreviousCandidates,
+    #[serde(rename="next_candidates")]
+    NextCandidates,
+    /// For buttons which only show a label
+    #[serde(rename="none")]
+    Nothing,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum Modifier {
+    Control,
+    Shift,
+    Lock,
+    #[serde(alias="Mod1")]
+    Alt,
+    Mod2,
+    Mod3,
+    Mod4,
+    Mod5,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Outline {
+    pub width: f64,
+    pub height: f64,
+}
+
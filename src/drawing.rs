@@ -6,13 +6,15 @@ use crate::action::{ Action, Modifier };
 use crate::keyboard;
 use crate::layout::{ Button, ButtonPosition, Label, LatchedState, Layout };
 use crate::layout::c::{ Bounds, EekGtkKeyboard, Point };
+use crate::submission::Submission;
 use crate::submission::c::Submission as CSubmission;
 
 use glib::translate::FromGlibPtrNone;
 use gtk::prelude::WidgetExt;
 
+use std::borrow::Cow;
 use std::collections::HashSet;
-use std::ffi::CStr;
+use std::ffi::{ CStr, CString };
 use std::ptr;
 
 mod c {
@@ -99,13 +101,16 @@ mod c {
                 layout.get_view_latched(),
                 &layout.state.current_view,
             );
+            let button = with_dynamic_label(layout, Some(&*submission), button);
             if state.pressed == keyboard::PressType::Pressed
                 || locked != LockedStyle::Free
+                // Base view doesn't know the label
+                || matches!(button, Cow::Owned(_))
             {
                 render_button_at_position(
                     renderer, &cr,
                     offset,
-                    button,
+                    &button,
                     state.pressed, locked,
                 );
             }
@@ -126,11 +131,21 @@ mod c {
             render_button_at_position(
                 renderer, &cr,
                 offset,
-                button,
+                &with_dynamic_label(layout, None, button),
                 keyboard::PressType::Released,
                 LockedStyle::Free,
             );
-        })
+        });
+
+        if let Some(switch) = &layout.state.side_switch {
+            render_button_at_position(
+                renderer, &cr,
+                Point { x: switch.bounds.x, y: switch.bounds.y },
+                &switch.get_button(),
+                keyboard::PressType::Released,
+                LockedStyle::Free,
+            );
+        }
     }
 }
 
@@ -168,6 +183,29 @@ impl LockedStyle {
     }
 }
 
+/// Replaces the label of buttons which reflect the layout state.
+fn with_dynamic_label<'a>(
+    layout: &Layout,
+    submission: Option<&Submission>,
+    button: &'a Button,
+) -> Cow<'a, Button> {
+    let label = match &button.action {
+        Action::CommitUnicode => layout.get_unicode_input()
+            .map(|pending| pending.get_label()),
+        Action::Candidate(slot) => submission
+            .and_then(|submission| submission.get_candidate(*slot))
+            .map(|text| CString::new(text).expect("Candidate contains NUL")),
+        _ => None,
+    };
+    match label {
+        Some(label) => Cow::Owned(Button {
+            label: Label::Text(label),
+            ..button.clone()
+        }),
+        None => Cow::Borrowed(button),
+    }
+}
+
 /// Renders a button at a position (button's own bounds ignored)
 fn render_button_at_position(
     renderer: c::EekRenderer,
@@ -18,11 +18,13 @@
 
 use crate::event_loop;
 use crate::logging;
+use crate::recording;
 use glib;
+use serde::Serialize;
 use std::sync::mpsc;
 use std::thread;
 use std::time::Instant;
-use super::{ActorState, Outcome};
+use super::{ActorState, Monitor, Outcome};
 
 // Traits
 use crate::logging::Warn;
@@ -57,22 +59,33 @@ where
 impl<S> Threaded<S>
 where
     // Not sure why this needs 'static. It's already owned.
-    S: ActorState + Send + 'static,
-    S::Event: Send,
+    S: ActorState + Send + Serialize + 'static,
+    S::Event: Send + Serialize,
     <S::Outcome as Outcome>::Commands: Send,
 {
-    pub fn new(
+    /// The `monitor` sees every event and every resulting state.
+    pub fn new<M: Monitor<S> + Send + 'static>(
         ui: UISender<S>,
         initial_state: S,
+        monitor: M,
     ) -> Self {
         let (sender, receiver) = mpsc::channel();
         let saved_sender = sender.clone();
         thread::spawn(move || {
             let mut state = event_loop::State::new(initial_state, Instant::now());
+            // Writes down the incoming events when the user asks for it.
+            let mut recorder = None;
             loop {
                 match receiver.recv() {
                     Ok(event) => {
-                        state = Self::handle_loop_event(&sender, state, event, &ui);
+                        state = Self::handle_loop_event(
+                            &sender,
+                            state,
+                            event,
+                            &ui,
+                            &mut recorder,
+                            &monitor,
+                        );
                     },
                     Err(e) => {
                         logging::print(logging::Level::Bug, &format!("Senders hung up, aborting: {}", e));
@@ -91,16 +104,37 @@ where
         self.thread.send(event)
     }
     
-    fn handle_loop_event(
+    fn handle_loop_event<M: Monitor<S>>(
         loop_sender: &mpsc::Sender<S::Event>,
         state: event_loop::State<S>,
         event: S::Event, 
         ui: &UISender<S>,
+        recorder: &mut Option<recording::Recorder>,
+        monitor: &M,
     ) -> event_loop::State<S> {
         let now = Instant::now();
 
+        match event.get_recording_request() {
+            Some(recording::Request::Start(path)) => {
+                *recorder = recording::Recorder::start(&path, &state.state, now)
+                    .or_warn(
+                        &mut logging::Print,
+                        logging::Problem::Warning,
+                        &format!("Can't record events to {}", path.display()),
+                    );
+            },
+            Some(recording::Request::Stop) => *recorder = None,
+            None => if let Some(recorder) = recorder {
+                recorder.record(&event, now);
+            },
+        }
+
+        monitor.report_event(&state.state, &event);
+
         let (new_state, commands) = event_loop::handle_event(state.clone(), event, now);
 
+        monitor.report_state(&new_state.state);
+
         ui.send(commands)
             .or_warn(&mut logging::Print, logging::Problem::Bug, "Can't send to UI");
 
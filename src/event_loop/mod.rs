@@ -38,6 +38,7 @@
 
 pub mod driver;
 
+use crate::recording;
 use std::cmp;
 use std::time::{ Duration, Instant };
 
@@ -48,6 +49,8 @@ pub trait Event: Clone {
     fn new_timeout_reached(when: Instant) -> Self;
     /// Returns the value of the reached timeout, if this event carries the timeout.
     fn get_timeout_reached(&self) -> Option<Instant>;
+    /// Returns the request to start or stop recording, if this event carries one.
+    fn get_recording_request(&self) -> Option<recording::Request>;
 }
 
 /// The externally observable state of the actor.
@@ -71,16 +74,24 @@ pub trait ActorState: Clone {
     fn get_next_wake(&self, now: Instant) -> Option<Instant>;
 }
 
+/// Watches the loop from the outside, without affecting it.
+pub trait Monitor<S: ActorState> {
+    /// Receives the event before it gets applied to the state.
+    fn report_event(&self, state: &S, event: &S::Event);
+    /// Receives the state after the event got applied.
+    fn report_state(&self, state: &S);
+}
+
 /// This keeps the state of the tracker loop between iterations
 #[derive(Clone)]
-struct State<S> {
-    state: S,
-    scheduled_wakeup: Option<Instant>,
+pub struct State<S> {
+    pub state: S,
+    pub scheduled_wakeup: Option<Instant>,
     last_update: Instant,
 }
 
 impl<S> State<S> {
-    fn new(initial_state: S, now: Instant) -> Self {
+    pub fn new(initial_state: S, now: Instant) -> Self {
         Self {
             state: initial_state,
             scheduled_wakeup: None,
@@ -95,7 +106,7 @@ impl<S> State<S> {
 /// - determines next scheduled animation wakeup,
 /// and because this is a pure function, it's easily testable.
 /// It returns the new state, and the message to send onwards.
-fn handle_event<S: ActorState>(
+pub fn handle_event<S: ActorState>(
     mut loop_state: State<S>,
     event: S::Event,
     now: Instant,
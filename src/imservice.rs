@@ -6,6 +6,7 @@
 use std::boxed::Box;
 use std::ffi::CString;
 use std::fmt;
+use std::mem;
 use std::num::Wrapping;
 use std::string::String;
 use std::time::Instant;
@@ -15,6 +16,7 @@ use crate::state;
 use crate::state::Event;
 use crate::logging;
 use crate::util::c::into_cstring;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 // Traits
 use std::convert::TryFrom;
@@ -50,6 +52,7 @@ pub mod c {
         #[allow(improper_ctypes)] // IMService will never be dereferenced in C
         pub fn imservice_connect_listeners(im: InputMethod, imservice: *const IMService);
         pub fn eek_input_method_commit_string(im: InputMethod, text: *const c_char);
+        pub fn eek_input_method_set_preedit_string(im: InputMethod, text: *const c_char, cursor_begin: i32, cursor_end: i32);
         pub fn eek_input_method_delete_surrounding_text(im: InputMethod, before: u32, after: u32);
         pub fn eek_input_method_commit(im: InputMethod, serial: u32);
     }
@@ -64,6 +67,7 @@ pub mod c {
     {
         let imservice = check_imservice(imservice, im).unwrap();
         imservice.preedit_string = String::new();
+        imservice.pending_preedit = String::new();
         imservice.pending = IMProtocolState {
             active: true,
             ..IMProtocolState::default()
@@ -76,6 +80,10 @@ pub mod c {
         im: InputMethod)
     {
         let imservice = check_imservice(imservice, im).unwrap();
+        // Focus is going away, so whatever is being composed is final.
+        // The current state is still active until "done".
+        imservice.flush_preedit()
+            .or_print(logging::Problem::Warning, "Can't commit preedit");
         imservice.pending = IMProtocolState {
             active: false,
             ..imservice.pending.clone()
@@ -220,13 +228,26 @@ bitflags!{
     }
 }
 
+/// Stored as the protocol value.
+impl Serialize for ContentHint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.bits().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentHint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(ContentHint::from_bits_truncate)
+    }
+}
+
 /// Map to `text_input_unstable_v3.content_purpose` values
 ///
 /// ```
 /// use rs::imservice::ContentPurpose;
 /// assert_eq!(ContentPurpose::Alpha as u32, 1);
 /// ```
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ContentPurpose {
     Normal = 0,
     Alpha = 1,
@@ -326,7 +347,10 @@ pub struct IMService {
 
     pending: IMProtocolState,
     current: IMProtocolState, // turn current into an idiomatic representation?
+    /// The preedit text shown in the application
     preedit_string: String,
+    /// Preedit text to show after the next commit
+    pending_preedit: String,
     serial: Wrapping<u32>,
 }
 
@@ -335,6 +359,32 @@ pub enum SubmitError {
     NotActive,
 }
 
+impl fmt::Display for SubmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmitError::NotActive => write!(f, "Input method not active"),
+        }
+    }
+}
+
+/// Receives the text submitted through the input method.
+///
+/// Implemented by `IMService`, and by fakes in tests.
+pub trait Sink {
+    fn commit_string(&self, text: &CString) -> Result<(), SubmitError>;
+    /// Places the preedit text with the cursor at its end.
+    /// Preedit is removed on commit unless set again.
+    fn set_preedit_string(&mut self, text: &CString) -> Result<(), SubmitError>;
+    fn get_preedit_string(&self) -> &str;
+    fn delete_surrounding_text(&self, before: u32, after: u32)
+        -> Result<(), SubmitError>;
+    fn commit(&mut self) -> Result<(), SubmitError>;
+    fn is_active(&self) -> bool;
+    fn get_content_purpose(&self) -> ContentPurpose;
+    /// Text preceding the cursor, as reported by the application.
+    fn get_text_before_cursor(&self) -> Option<&str>;
+}
+
 impl IMService {
     pub fn new(
         im: c::InputMethod,
@@ -348,6 +398,7 @@ impl IMService {
             pending: IMProtocolState::default(),
             current: IMProtocolState::default(),
             preedit_string: String::new(),
+            pending_preedit: String::new(),
             serial: Wrapping(0u32),
         });
         unsafe {
@@ -359,7 +410,37 @@ impl IMService {
         imservice
     }
 
-    pub fn commit_string(&self, text: &CString) -> Result<(), SubmitError> {
+    /// Commits the preedit text as it is.
+    fn flush_preedit(&mut self) -> Result<(), SubmitError> {
+        if self.preedit_string.is_empty() {
+            return Ok(());
+        }
+        let text = CString::new(self.preedit_string.clone())
+            .expect("Preedit contains NUL");
+        self.commit_string(&text)
+            .and_then(|()| self.commit())
+    }
+
+    fn send_event(&self) {
+        let state = &self.current;
+        let timestamp = Instant::now();
+        let message = if state.active {
+            state::InputMethod::Active(
+                state::InputMethodDetails {
+                    hint: state.content_hint,
+                    purpose: state.content_purpose,
+                }
+            )
+        } else {
+            state::InputMethod::InactiveSince(timestamp)
+        };
+        self.sender.send(Event::InputMethod(message))
+            .or_warn(&mut logging::Print, logging::Problem::Warning, "Can't send to state manager");
+    }
+}
+
+impl Sink for IMService {
+    fn commit_string(&self, text: &CString) -> Result<(), SubmitError> {
         match self.current.active {
             true => {
                 unsafe {
@@ -371,7 +452,29 @@ impl IMService {
         }
     }
 
-    pub fn delete_surrounding_text(
+    fn set_preedit_string(&mut self, text: &CString) -> Result<(), SubmitError> {
+        match self.current.active {
+            true => {
+                let cursor = text.as_bytes().len() as i32;
+                unsafe {
+                    c::eek_input_method_set_preedit_string(
+                        self.im,
+                        text.as_ptr(),
+                        cursor, cursor,
+                    )
+                }
+                self.pending_preedit = text.to_string_lossy().into_owned();
+                Ok(())
+            },
+            false => Err(SubmitError::NotActive),
+        }
+    }
+
+    fn get_preedit_string(&self) -> &str {
+        &self.preedit_string
+    }
+
+    fn delete_surrounding_text(
         &self,
         before: u32, after: u32,
     ) -> Result<(), SubmitError> {
@@ -389,36 +492,29 @@ impl IMService {
         }
     }
 
-    pub fn commit(&mut self) -> Result<(), SubmitError> {
+    fn commit(&mut self) -> Result<(), SubmitError> {
         match self.current.active {
             true => {
                 unsafe {
                     c::eek_input_method_commit(self.im, self.serial.0)
                 }
+                self.preedit_string = mem::take(&mut self.pending_preedit);
                 Ok(())
             },
             false => Err(SubmitError::NotActive),
         }
     }
 
-    pub fn is_active(&self) -> bool {
+    fn is_active(&self) -> bool {
         self.current.active
     }
 
-    fn send_event(&self) {
-        let state = &self.current;
-        let timestamp = Instant::now();
-        let message = if state.active {
-            state::InputMethod::Active(
-                state::InputMethodDetails {
-                    hint: state.content_hint,
-                    purpose: state.content_purpose,
-                }
-            )
-        } else {
-            state::InputMethod::InactiveSince(timestamp)
-        };
-        self.sender.send(Event::InputMethod(message))
-            .or_warn(&mut logging::Print, logging::Problem::Warning, "Can't send to state manager");
+    fn get_content_purpose(&self) -> ContentPurpose {
+        self.current.content_purpose
+    }
+
+    fn get_text_before_cursor(&self) -> Option<&str> {
+        let text = self.current.surrounding_text.to_str().ok()?;
+        text.get(..self.current.surrounding_cursor as usize)
     }
 }
@@ -15,6 +15,7 @@
 enum squeek_arrangement_kind {
     ARRANGEMENT_KIND_BASE = 0,
     ARRANGEMENT_KIND_WIDE = 1,
+    ARRANGEMENT_KIND_SPLIT = 2,
 };
 
 struct squeek_layout_state {
@@ -29,7 +30,7 @@ struct squeek_layout;
 
 
 struct transformation squeek_layout_calculate_transformation(
-        const struct squeek_layout *layout,
+        struct squeek_layout *layout,
         double allocation_width, double allocation_size);
 
 struct squeek_layout *squeek_load_layout(const char *name, uint32_t type, uint32_t variant_type, const char *overlay_name);
@@ -19,6 +19,7 @@
 
 use gdk::prelude::SettingsExt;
 use gio::Settings;
+use std::cell::Cell;
 use std::cmp;
 use std::collections::HashMap;
 use std::ffi::CString;
@@ -27,6 +28,8 @@ use std::vec::Vec;
 
 use crate::action::Action;
 use crate::actors;
+use crate::actors::external::debug;
+use crate::composition;
 use crate::drawing;
 use crate::float_ord::FloatOrd;
 use crate::keyboard::{KeyState, KeyCode, PressType};
@@ -34,9 +37,11 @@ use crate::logging;
 use crate::popover;
 use crate::receiver;
 use crate::submission::{ Submission, SubmitData, Timestamp };
-use crate::util::find_max_double;
+use crate::unicode_input;
+use crate::util::{ find_max_double, Rational };
 
 use crate::imservice::ContentPurpose;
+use serde::{Deserialize, Serialize};
 
 // Traits
 use crate::logging::Warn;
@@ -48,6 +53,8 @@ pub mod c {
     use crate::receiver;
     use crate::submission::c::Submission as CSubmission;
 
+    use crate::state;
+
     use gtk_sys;
     use std::ops::{ Add, Sub };
     use std::os::raw::c_void;
@@ -137,7 +144,7 @@ pub mod c {
                 scale_y: self.scale_y * next.scale_y,
             }
         }
-        fn forward(&self, p: Point) -> Point {
+        pub fn forward(&self, p: Point) -> Point {
             Point {
                 x: (p.x - self.origin_x) / self.scale_x,
                 y: (p.y - self.origin_y) / self.scale_y,
@@ -176,12 +183,12 @@ pub mod c {
     #[no_mangle]
     pub extern "C"
     fn squeek_layout_calculate_transformation(
-        layout: *const Layout,
+        layout: *mut Layout,
         allocation_width: f64,
         allocation_height: f64,
     ) -> Transformation {
-        let layout = unsafe { &*layout };
-        layout.shape.calculate_transformation(Size {
+        let layout = unsafe { &mut *layout };
+        layout.calculate_transformation(Size {
             width: allocation_width,
             height: allocation_height,
         })
@@ -235,6 +242,13 @@ pub mod c {
                 keyboard: ui_keyboard,
             };
 
+            if let Some(ResizeHandle { resizing: true, .. })
+                = layout.state.resize_handle.take()
+            {
+                app_state.send(state::Event::PanelResize(state::resize::Event::Done))
+                    .or_print(logging::Problem::Bug, "Can't send to state");
+            }
+
             // The list must be copied,
             // because it will be mutated in the loop
             let pressed_buttons
@@ -263,6 +277,7 @@ pub mod c {
             let layout = unsafe { &mut *layout };
             let submission = submission.clone_ref();
             let mut submission = submission.borrow_mut();
+            layout.state.resize_handle = None;
             // The list must be copied,
             // because it will be mutated in the loop
             let pressed_buttons = layout.state.active_buttons.clone();
@@ -309,12 +324,36 @@ pub mod c {
                     Timestamp(time),
                     &button,
                 );
+                if let Some(Action::ShowPreferences)
+                    = layout.shape.get_button(&button).map(|b| &b.action)
+                {
+                    layout.state.resize_handle = Some(ResizeHandle {
+                        button,
+                        start_y: y_widget,
+                        start_time: time,
+                        resizing: false,
+                    });
+                }
                 // maybe TODO: draw on the display buffer here
                 drawing::queue_redraw(ui_keyboard);
                 unsafe {
                     eek_gtk_keyboard_emit_feedback(ui_keyboard);
                 }
-            };
+            } else if let Some(switch) = &layout.state.side_switch {
+                if switch.bounds.contains(&point) {
+                    // The setting reaches the state,
+                    // which then moves the layout.
+                    Settings::new("sm.puri.Squeekboard")
+                        .set_string("one-handed-side", switch.side.opposite().to_setting())
+                        .or_print(
+                            logging::Problem::Warning,
+                            "Can't switch the side of the one-handed mode",
+                        );
+                    unsafe {
+                        eek_gtk_keyboard_emit_feedback(ui_keyboard);
+                    }
+                }
+            }
         }
 
         // FIXME: this will work funny
@@ -348,6 +387,34 @@ pub mod c {
                 Point { x: x_widget, y: y_widget }
             );
 
+            if let Some(handle) = layout.state.resize_handle.clone() {
+                let offset = y_widget - handle.start_y;
+                let moved = offset.abs() > RESIZE_THRESHOLD;
+                let held = time.0.wrapping_sub(handle.start_time) >= RESIZE_HOLD;
+                if !handle.resizing && moved && !held {
+                    // An ordinary drag, away from the button.
+                    layout.state.resize_handle = None;
+                } else if handle.resizing || moved {
+                    if !handle.resizing {
+                        // Not a press of the button any more.
+                        layout.state.active_buttons.remove(&handle.button);
+                    }
+                    // The state knows where the panel is anchored,
+                    // and how tall it is.
+                    app_state
+                        .send(state::Event::PanelResize(state::resize::Event::Drag {
+                            offset: offset as i32,
+                        }))
+                        .or_print(logging::Problem::Bug, "Can't send to state");
+                    layout.state.resize_handle = Some(ResizeHandle {
+                        resizing: true,
+                        ..handle
+                    });
+                    drawing::queue_redraw(ui_keyboard);
+                    return;
+                }
+            }
+
             let pressed_buttons = layout.state.active_buttons.clone();
             let pressed_buttons = pressed_buttons.iter_pressed();
             let button_info = layout.find_index_by_position(point);
@@ -512,6 +579,58 @@ impl Row {
         &self.buttons
     }
 
+    /// Makes space in the middle of the row.
+    /// A wide button across the middle, like the space bar, gets stretched.
+    /// Otherwise, the row is split between the buttons closest to the middle.
+    fn with_gap(self, gap: f64) -> Row {
+        enum Split {
+            Stretch(usize),
+            Before(usize),
+        }
+
+        let middle = self.size.width / 2.0;
+        let narrowest = self.buttons.iter()
+            .map(|(_offset, button)| button.size.width)
+            .fold(f64::INFINITY, f64::min);
+        let across_middle = self.buttons.iter()
+            .position(|(offset, button)| {
+                *offset < middle && offset + button.size.width > middle
+            });
+
+        let split = match across_middle {
+            Some(index) if self.buttons[index].1.size.width >= 2.0 * narrowest
+                => Split::Stretch(index),
+            _ => {
+                let closest = self.buttons.iter()
+                    .enumerate()
+                    // Splitting before the first button makes no gap.
+                    .skip(1)
+                    .min_by_key(|(_index, (offset, _button))| {
+                        FloatOrd((offset - middle).abs())
+                    });
+                match closest {
+                    Some((index, _)) => Split::Before(index),
+                    None => return self,
+                }
+            },
+        };
+
+        let buttons = self.buttons.into_iter()
+            .enumerate()
+            .map(|(index, (offset, mut button))| match split {
+                Split::Stretch(s) if index == s => {
+                    button.size.width += gap;
+                    (offset, button)
+                },
+                Split::Stretch(s) | Split::Before(s) if index >= s => {
+                    (offset + gap, button)
+                },
+                _ => (offset, button),
+            })
+            .collect();
+        Row::new(buttons)
+    }
+
     /// Finds the first button that covers the specified point
     /// relative to row's position's origin.
     /// Returns its index too.
@@ -618,6 +737,15 @@ impl View {
         self.size.clone()
     }
 
+    /// Makes space in the middle of each row.
+    fn with_gap(self, gap: f64) -> View {
+        View::new(
+            self.rows.into_iter()
+                .map(|(offset, row)| (offset.y, row.with_gap(gap)))
+                .collect()
+        )
+    }
+
     /// Returns positioned rows, with appropriate x offsets (centered)
     pub fn get_rows(&self) -> &Vec<(c::Point, Row)> {
         &self.rows
@@ -639,11 +767,130 @@ impl View {
     }
 }
 
+/// How much space the layout needs on the panel
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Proportions {
+    /// Rows in the tallest view
+    pub rows: u32,
+    /// Height divided by width, including margins
+    pub aspect_ratio: Rational<i32>,
+}
+
 /// The physical characteristic of layout for the purpose of styling
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum ArrangementKind {
     Base = 0,
     Wide = 1,
+    /// Halves within the reach of thumbs, on large screens
+    Split = 2,
+}
+
+/// Space between the halves of a split layout,
+/// relative to the width of the layout before splitting.
+const SPLIT_GAP: f64 = 0.3;
+
+/// Side of the panel where the layout is docked in one-handed mode
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    pub fn from_settings(gsettings: &Settings) -> Option<Side> {
+        match gsettings.boolean("one-handed") {
+            false => None,
+            true => Some(match gsettings.string("one-handed-side").as_str() {
+                "left" => Side::Left,
+                _ => Side::Right,
+            }),
+        }
+    }
+
+    fn to_setting(self) -> &'static str {
+        match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        }
+    }
+
+    fn opposite(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+}
+
+thread_local! {
+    /// Layouts are owned by the UI, so the state's choice gets stored here.
+    static ONE_HANDED: Cell<Option<Side>> = Cell::new(None);
+}
+
+/// Docks the layouts to the side, starting with the next transformation.
+/// Must be called on the UI thread.
+pub fn set_one_handed(side: Option<Side>) {
+    ONE_HANDED.with(|one_handed| one_handed.set(side));
+}
+
+/// Part of the panel width taken by the layout in one-handed mode
+const ONE_HANDED_WIDTH: f64 = 0.75;
+
+/// In one-handed mode, the empty part of the panel
+/// is a button which moves the layout to the other side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SideSwitch {
+    /// Where the layout is docked now
+    side: Side,
+    /// In layout coordinates, like the buttons
+    pub bounds: c::Bounds,
+}
+
+impl SideSwitch {
+    fn new(
+        side: Side,
+        available: &Size,
+        widget_to_layout: &c::Transformation,
+    ) -> SideSwitch {
+        let width = available.width * (1.0 - ONE_HANDED_WIDTH);
+        let x = match side {
+            Side::Left => available.width - width,
+            Side::Right => 0.0,
+        };
+        let start = widget_to_layout.forward(c::Point { x, y: 0.0 });
+        let end = widget_to_layout.forward(c::Point {
+            x: x + width,
+            y: available.height,
+        });
+        SideSwitch {
+            side,
+            bounds: c::Bounds {
+                x: start.x,
+                y: start.y,
+                width: end.x - start.x,
+                height: end.y - start.y,
+            },
+        }
+    }
+
+    /// The arrow points to where the layout goes.
+    pub fn get_button(&self) -> Button {
+        let icon = match self.side {
+            Side::Left => "go-next-symbolic",
+            Side::Right => "go-previous-symbolic",
+        };
+        Button {
+            name: CString::new("squeekboard_side_switch").unwrap(),
+            label: Label::IconName(CString::new(icon).unwrap()),
+            size: Size {
+                width: self.bounds.width,
+                height: self.bounds.height,
+            },
+            outline_name: CString::new("special").unwrap(),
+            keycodes: Vec::new(),
+            action: Action::SwitchSide,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -735,8 +982,36 @@ pub struct LayoutState {
     /// Latched/locked appearance is derived from current view
     /// and button metadata.
     pub active_buttons: ActiveButtons,
+    /// Code point being entered, if in the middle of that.
+    unicode_input: Option<unicode_input::Pending>,
+    /// The preferences button is being held, and may get dragged.
+    resize_handle: Option<ResizeHandle>,
+    /// Present in one-handed mode.
+    pub side_switch: Option<SideSwitch>,
 }
 
+/// The preferences button doubles as a handle for resizing the panel.
+/// Holding it for a moment and then dragging it up or down
+/// changes the height of the panel,
+/// and then releasing it doesn't open the popover.
+/// Moving it sooner is an ordinary press, like for other buttons.
+#[derive(Clone, Debug)]
+struct ResizeHandle {
+    button: ButtonPosition,
+    /// Where the press started, in widget coordinates
+    start_y: f64,
+    /// When the press started, in milliseconds
+    start_time: u32,
+    /// The handle got dragged, and the panel is being resized.
+    resizing: bool,
+}
+
+/// How far the handle must move before resizing starts, in pixels.
+const RESIZE_THRESHOLD: f64 = 10.0;
+
+/// How long the handle must be held before it can be dragged, in milliseconds.
+const RESIZE_HOLD: u32 = 500;
+
 /// A builder structure for picking up layout data from storage
 pub struct LayoutParseData {
     /// Point is the offset within the panel
@@ -745,6 +1020,38 @@ pub struct LayoutParseData {
     /// xkb keymaps applicable to the contained keys
     pub keymaps: Vec<CString>,
     pub margins: Margins,
+    pub composition: Option<composition::Kind>,
+}
+
+impl LayoutParseData {
+    /// Moves the halves of each row apart.
+    pub fn split(self) -> LayoutParseData {
+        let size = View::calculate_super_size(
+            self.views.values().map(|(_offset, view)| view).collect()
+        );
+        let gap = size.width * SPLIT_GAP;
+        let views: Vec<_> = self.views.into_iter()
+            .map(|(name, (_offset, view))| (name, view.with_gap(gap)))
+            .collect();
+
+        // Center views on the same point again.
+        let size = View::calculate_super_size(
+            views.iter().map(|(_name, view)| view).collect()
+        );
+        let views = views.into_iter()
+            .map(|(name, view)| (
+                name,
+                (
+                    c::Point {
+                        x: (size.width - view.get_size().width) / 2.0,
+                        y: (size.height - view.get_size().height) / 2.0,
+                    },
+                    view,
+                ),
+            ))
+            .collect();
+        LayoutParseData { views, ..self }
+    }
 }
 
 /// Static, cacheable information for the layout
@@ -762,6 +1069,8 @@ pub struct LayoutData {
     // Non-UI stuff
     /// xkb keymaps applicable to the contained keys. Unchangeable
     pub keymaps: Vec<CString>,
+    /// Text composition engine to pass submitted text through
+    pub composition: Option<composition::Kind>,
 }
 
 #[derive(Debug)]
@@ -787,6 +1096,19 @@ impl LayoutData {
         procedures::find_button_place(view, (button.row, button.position_in_row))
     }
     
+    /// Number of composition candidates which can be shown at once.
+    pub fn get_candidate_count(&self) -> usize {
+        self.views.values()
+            .flat_map(|(_offset, view)| view.get_rows().iter())
+            .flat_map(|(_offset, row)| row.get_buttons().iter())
+            .filter_map(|(_offset, button)| match button.action {
+                Action::Candidate(slot) => Some(slot + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
     /// Calculates size without margins
     fn calculate_inner_size(&self) -> Size {
         View::calculate_super_size(
@@ -794,6 +1116,23 @@ impl LayoutData {
         )
     }
 
+    /// Describes the layout for sizing the panel,
+    /// in the same way for every size of the panel.
+    pub fn get_proportions(&self) -> Proportions {
+        let size = self.calculate_size();
+        Proportions {
+            rows: self.views.values()
+                .map(|(_offset, view)| view.rows.len() as u32)
+                .max()
+                .unwrap_or(0),
+            // Hundredths of a unit are precise enough to size the panel.
+            aspect_ratio: Rational {
+                numerator: (size.height * 100.0).round() as i32,
+                denominator: (size.width * 100.0).round() as u32,
+            },
+        }
+    }
+
     /// Size including margins
     fn calculate_size(&self) -> Size {
         let inner_size = self.calculate_inner_size();
@@ -810,9 +1149,38 @@ impl LayoutData {
     pub fn calculate_transformation(
         &self,
         available: Size,
+        one_handed: Option<Side>,
     ) -> c::Transformation {
         let gsettings = Settings::new("sm.puri.Squeekboard");
         let stretch_layout_to_fit_panel = gsettings.boolean ("layout-shape-changes-to-fit-panel");
+        self.calculate_docked_transformation(
+            available,
+            stretch_layout_to_fit_panel,
+            one_handed,
+        )
+    }
+
+    /// In one-handed mode, the layout gets squeezed into a part of the panel.
+    /// The rest stays empty, and presses there don't reach any button.
+    fn calculate_docked_transformation(
+        &self,
+        available: Size,
+        stretch_layout_to_fit_panel: bool,
+        one_handed: Option<Side>,
+    ) -> c::Transformation {
+        let (available, dock_x) = match one_handed {
+            None => (available, 0.0),
+            Some(side) => {
+                let width = available.width * ONE_HANDED_WIDTH;
+                (
+                    Size { width, height: available.height },
+                    match side {
+                        Side::Left => 0.0,
+                        Side::Right => available.width - width,
+                    },
+                )
+            },
+        };
 
         let layout_stretching_limit: f64;
         if stretch_layout_to_fit_panel == true {
@@ -821,6 +1189,7 @@ impl LayoutData {
             if self.kind == ArrangementKind::Base { layout_stretching_limit = 5.0 }
             // The "Wide"-layout-shape is also used on monitors,
             // and thus should not stretch more than necessary.
+            // The same goes for the "Split" one, used on tablets.
             else { layout_stretching_limit = 1.4 }
         }
         else { layout_stretching_limit = 1.0 }
@@ -840,7 +1209,7 @@ impl LayoutData {
         let scale_y = if stretch_layout_to_fit_panel == true && h_scale / v_scale > 0.49 { v_scale }
                       else { cmp::min(FloatOrd(h_scale), FloatOrd(v_scale)).0 };
         let outside_margins = c::Transformation {
-            origin_x: (available.width - (scale_x * size.width)) / 2.0,
+            origin_x: dock_x + (available.width - (scale_x * size.width)) / 2.0,
             origin_y: (available.height - (scale_y * size.height)) / 2.0,
             scale_x,
             scale_y,
@@ -866,16 +1235,33 @@ impl Layout {
                 views: data.views,
                 keymaps: data.keymaps,
                 margins: data.margins,
+                composition: data.composition,
                 purpose,
             },
             state: LayoutState {
                 current_view: "base".to_owned(),
                 view_latched: LatchedState::Not,
                 active_buttons: ActiveButtons(HashMap::new()),
+                unicode_input: None,
+                resize_handle: None,
+                side_switch: None,
             },
         }
     }
 
+    /// Also places the side switch, if needed.
+    pub fn calculate_transformation(&mut self, available: Size) -> c::Transformation {
+        let one_handed = ONE_HANDED.with(Cell::get);
+        let transformation = self.shape.calculate_transformation(
+            available.clone(),
+            one_handed,
+        );
+        self.state.side_switch = one_handed.map(|side| {
+            SideSwitch::new(side, &available, &transformation)
+        });
+        transformation
+    }
+
     pub fn get_current_view_position(&self) -> &(c::Point, View) {
         &self.shape.views
             .get(&self.state.current_view).expect("Selected nonexistent view")
@@ -887,6 +1273,7 @@ impl Layout {
 
     fn set_view(&mut self, view: String) -> Result<(), NoSuchView> {
         if self.shape.views.contains_key(&view) {
+            debug::set_view(&view);
             self.state.current_view = view;
             Ok(())
         } else {
@@ -900,6 +1287,59 @@ impl Layout {
         &self.state.view_latched
     }
     
+    pub fn get_unicode_input(&self) -> Option<&unicode_input::Pending> {
+        self.state.unicode_input.as_ref()
+    }
+
+    /// Updates the code point entry state.
+    /// Returns the text to submit when the entry is finished.
+    fn apply_unicode_input(&mut self, action: &Action) -> Option<String> {
+        match (action, &mut self.state.unicode_input) {
+            (Action::UnicodeInput(view), None) => {
+                let pending = unicode_input::Pending::new(
+                    self.state.current_view.clone(),
+                );
+                self.state.unicode_input = Some(pending);
+                try_set_view(self, view);
+                None
+            },
+            // Pressing the same button again cancels.
+            (Action::UnicodeInput(_), Some(pending)) => {
+                let view = pending.return_view.clone();
+                self.state.unicode_input = None;
+                try_set_view(self, &view);
+                None
+            },
+            (Action::HexDigit(digit), Some(pending)) => {
+                pending.push(*digit);
+                None
+            },
+            (Action::HexErase, Some(pending)) => {
+                pending.pop();
+                None
+            },
+            (Action::CommitUnicode, Some(pending)) => {
+                // An invalid code point stays, so that it can be corrected.
+                let c = pending.get_char()?;
+                let view = pending.return_view.clone();
+                self.state.unicode_input = None;
+                try_set_view(self, &view);
+                Some(c.to_string())
+            },
+            (Action::HexDigit(_), None)
+                | (Action::HexErase, None)
+                | (Action::CommitUnicode, None)
+            => {
+                log_print!(
+                    logging::Level::Surprise,
+                    "Code point entry button used outside of code point entry",
+                );
+                None
+            },
+            _ => None,
+        }
+    }
+
     /// Returns index within current view
     fn find_index_by_position(&self, point: c::Point) -> Option<(usize, usize)> {
         let (offset, view) = self.get_current_view_position();
@@ -1211,6 +1651,9 @@ mod seat {
                     }
                 }
             },
+            Action::Candidate(slot) => submission.select_candidate(slot),
+            Action::PreviousCandidates => submission.turn_candidate_page(false),
+            Action::NextCandidates => submission.turn_candidate_page(true),
             // Other keys are handled in view switcher before.
             _ => {}
         };
@@ -1245,6 +1688,9 @@ mod seat {
         );
         
         // Apply state changes
+        if let Some(text) = layout.apply_unicode_input(&action) {
+            submission.submit_text(&text, time);
+        }
         layout.apply_view_transition(&action);
         
         if let Presence::Missing = layout.state.active_buttons.remove(&button_pos) {
@@ -1254,6 +1700,131 @@ mod seat {
             );
         }
     }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        use crate::action::Modifier;
+        use crate::data::parsing;
+        use crate::keyboard::Modifiers;
+        use crate::logging::ProblemPanic;
+        use crate::submission::test::{ make_submission, Sent };
+
+        fn load(name: &str) -> Layout {
+            let data = parsing::Layout::from_resource(name)
+                .unwrap()
+                .build(ProblemPanic).0
+                .unwrap();
+            Layout::new(data, ArrangementKind::Base, ContentPurpose::Normal)
+        }
+
+        /// Finds the button in the current view.
+        fn find(layout: &Layout, name: &str) -> ButtonPosition {
+            let view_name = &layout.state.current_view;
+            let (_, view) = &layout.shape.views[view_name];
+            view.get_rows().iter().enumerate()
+                .find_map(|(row_idx, (_, row))| {
+                    row.get_buttons().iter()
+                        .position(|(_, button)| button.name.to_str() == Ok(name))
+                        .map(|position_in_row| ButtonPosition {
+                            view: view_name.clone(),
+                            row: row_idx,
+                            position_in_row,
+                        })
+                })
+                .unwrap()
+        }
+
+        fn click(layout: &mut Layout, submission: &mut Submission, name: &str) {
+            let button = find(layout, name);
+            handle_press_key(layout, submission, Timestamp(0), &button);
+            handle_release_key(layout, submission, None, Timestamp(0), None, &button);
+        }
+
+        fn get_keycode(layout: &Layout, name: &str) -> u32 {
+            let button = layout.shape.get_button(&find(layout, name)).unwrap();
+            assert_eq!(button.keycodes.len(), 1);
+            button.keycodes[0].code
+        }
+
+        fn pressed_and_released(code: u32) -> [Sent; 2] {
+            [
+                Sent::Key(code, PressType::Pressed),
+                Sent::Key(code, PressType::Released),
+            ]
+        }
+
+        #[test]
+        fn keycodes_with_modifiers() {
+            let mut layout = load("terminal/us");
+            let (mut submission, log) = make_submission(false);
+            submission.use_layout(&layout.shape, Timestamp(0));
+            assert_eq!(
+                log.borrow_mut().drain(..).collect::<Vec<_>>(),
+                vec![
+                    Sent::KeyMap(layout.shape.keymaps[0].clone()),
+                    Sent::Modifiers(Modifiers::empty()),
+                ],
+            );
+
+            let c = get_keycode(&layout, "c");
+            click(&mut layout, &mut submission, "Ctrl");
+            assert!(submission.is_modifier_active(Modifier::Control));
+            click(&mut layout, &mut submission, "c");
+            click(&mut layout, &mut submission, "Ctrl");
+            click(&mut layout, &mut submission, "c");
+
+            let mut expected = vec![Sent::Modifiers(Modifiers::CONTROL)];
+            expected.extend(pressed_and_released(c));
+            expected.push(Sent::Modifiers(Modifiers::empty()));
+            expected.extend(pressed_and_released(c));
+            assert_eq!(log.borrow().clone(), expected);
+        }
+
+        /// The upper case view latches for one key, and needs no modifier.
+        #[test]
+        fn latched_view() {
+            let mut layout = load("us");
+            let (mut submission, log) = make_submission(false);
+            submission.use_layout(&layout.shape, Timestamp(0));
+            log.borrow_mut().clear();
+
+            click(&mut layout, &mut submission, "show_upper");
+            assert_eq!(&layout.state.current_view, "upper");
+            let upper_a = get_keycode(&layout, "A");
+            click(&mut layout, &mut submission, "A");
+            assert_eq!(&layout.state.current_view, "base");
+            let a = get_keycode(&layout, "a");
+            click(&mut layout, &mut submission, "a");
+
+            let mut expected = Vec::from(pressed_and_released(upper_a));
+            expected.extend(pressed_and_released(a));
+            assert_eq!(log.borrow().clone(), expected);
+        }
+
+        /// Text goes to the input method, unless a modifier is active.
+        #[test]
+        fn text_with_im() {
+            let mut layout = load("terminal/us");
+            let (mut submission, log) = make_submission(true);
+            submission.use_layout(&layout.shape, Timestamp(0));
+            log.borrow_mut().clear();
+
+            let space = get_keycode(&layout, "space");
+            click(&mut layout, &mut submission, "space");
+            click(&mut layout, &mut submission, "Alt");
+            click(&mut layout, &mut submission, "space");
+
+            let mut expected = vec![
+                Sent::CommitString(" ".into()),
+                Sent::Commit,
+                Sent::Modifiers(Modifiers::MOD1),
+            ];
+            expected.extend(pressed_and_released(space));
+            assert_eq!(log.borrow().clone(), expected);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1341,9 +1912,12 @@ mod test {
                 current_view: "base".into(),
                 view_latched: LatchedState::Not,
                 active_buttons: ActiveButtons(HashMap::new()),
+                unicode_input: None,
+                resize_handle: None,
             },
             shape: LayoutData {
                 keymaps: Vec::new(),
+                composition: None,
                 kind: ArrangementKind::Base,
                 margins: Margins {
                     top: 0.0,
@@ -1421,9 +1995,12 @@ mod test {
                 current_view: "base".into(),
                 view_latched: LatchedState::Not,
                 active_buttons: ActiveButtons(HashMap::new()),
+                unicode_input: None,
+                resize_handle: None,
             },
             shape: LayoutData {
                 keymaps: Vec::new(),
+                composition: None,
                 kind: ArrangementKind::Base,
                 margins: Margins {
                     top: 0.0,
@@ -1492,9 +2069,12 @@ mod test {
                 current_view: "base".into(),
                 view_latched: LatchedState::Not,
                 active_buttons: ActiveButtons(HashMap::new()),
+                unicode_input: None,
+                resize_handle: None,
             },
             shape: LayoutData {
                 keymaps: Vec::new(),
+                composition: None,
                 kind: ArrangementKind::Base,
                 margins: Margins {
                     top: 0.0,
@@ -1526,6 +2106,73 @@ mod test {
         assert_eq!(&layout.state.current_view, "base");
     }
 
+    #[test]
+    fn unicode_input_commit() {
+        let enter = Action::UnicodeInput("hex".into());
+        let view = View::new(vec![(
+            0.0,
+            Row::new(vec![(
+                0.0,
+                Button {
+                    action: enter.clone(),
+                    ..make_button("unicode".into())
+                },
+            )]),
+        )]);
+
+        let mut layout = Layout {
+            state: LayoutState {
+                current_view: "base".into(),
+                view_latched: LatchedState::Not,
+                active_buttons: ActiveButtons(HashMap::new()),
+                unicode_input: None,
+                resize_handle: None,
+            },
+            shape: LayoutData {
+                keymaps: Vec::new(),
+                composition: None,
+                kind: ArrangementKind::Base,
+                margins: Margins {
+                    top: 0.0,
+                    left: 0.0,
+                    right: 0.0,
+                    bottom: 0.0,
+                },
+                views: hashmap! {
+                    "base".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
+                    "hex".into() => (c::Point { x: 0.0, y: 0.0 }, view),
+                },
+                purpose: ContentPurpose::Normal,
+            },
+        };
+
+        assert_eq!(layout.apply_unicode_input(&enter), None);
+        assert_eq!(&layout.state.current_view, "hex");
+        for digit in &[0xd, 8, 0, 0] {
+            layout.apply_unicode_input(&Action::HexDigit(*digit));
+        }
+        // A surrogate is not a character, so the entry continues.
+        assert_eq!(layout.apply_unicode_input(&Action::CommitUnicode), None);
+        assert_eq!(&layout.state.current_view, "hex");
+        layout.apply_unicode_input(&Action::HexErase);
+        layout.apply_unicode_input(&Action::HexErase);
+        layout.apply_unicode_input(&Action::HexErase);
+        layout.apply_unicode_input(&Action::HexDigit(0xc));
+        assert_eq!(
+            layout.apply_unicode_input(&Action::CommitUnicode),
+            Some("\u{dc}".into()),
+        );
+        assert_eq!(&layout.state.current_view, "base");
+        assert!(layout.get_unicode_input().is_none());
+
+        // Cancelling returns to the original view too.
+        layout.apply_unicode_input(&enter);
+        layout.apply_unicode_input(&Action::HexDigit(0xa));
+        assert_eq!(layout.apply_unicode_input(&enter), None);
+        assert_eq!(&layout.state.current_view, "base");
+        assert!(layout.get_unicode_input().is_none());
+    }
+
     #[test]
     fn check_centering() {
         //    A B
@@ -1581,6 +2228,34 @@ mod test {
         );
     }
 
+    #[test]
+    fn split_row() {
+        let button = |name: &str, width| Button {
+            size: Size { width, height: 10.0 },
+            ..make_button(name.into())
+        };
+        let letters = Row::new(vec![
+            (0.0, button("A", 10.0)),
+            (10.0, button("B", 10.0)),
+            (20.0, button("C", 10.0)),
+            (30.0, button("D", 10.0)),
+        ]).with_gap(5.0);
+        let offsets: Vec<_> = letters.get_buttons().iter()
+            .map(|(offset, _button)| *offset)
+            .collect();
+        assert_eq!(offsets, vec![0.0, 10.0, 25.0, 35.0]);
+        assert_eq!(letters.get_size().width, 45.0);
+
+        let spaceline = Row::new(vec![
+            (0.0, button("A", 10.0)),
+            (10.0, button("space", 30.0)),
+            (40.0, button("B", 10.0)),
+        ]).with_gap(5.0);
+        let buttons = spaceline.get_buttons();
+        assert_eq!(buttons[1].1.size.width, 35.0);
+        assert_eq!(buttons[2].0, 45.0);
+    }
+
     #[test]
     fn check_bottom_margin() {
         // TODO: This should work correctly independent from the current settings on the system.
@@ -1601,6 +2276,7 @@ mod test {
         ]);
         let layout = LayoutData {
             keymaps: Vec::new(),
+            composition: None,
             kind: ArrangementKind::Base,
             // Lots of bottom margin
             margins: Margins {
@@ -1625,7 +2301,8 @@ mod test {
         // Do not change these values randomly,
         // because these are comparable due to incidentally precise float-representation.
         let transformation = layout.calculate_transformation(
-            Size { width: 2.0, height: 2.0 } // Panel with a size of 2x2 pixels.
+            Size { width: 2.0, height: 2.0 }, // Panel with a size of 2x2 pixels.
+            None,
         );
         let scale_x_comparison = if stretch_layout_to_fit_panel == true { 2.0 } else { 1.0 };
         let origin_x_comparison = if stretch_layout_to_fit_panel == true { 0.0 } else { 0.5 };
@@ -1635,6 +2312,104 @@ mod test {
         assert_eq!(transformation.origin_y, 0.0, "transformation.origin_y changed.");
     }
 
+    #[test]
+    fn one_handed() {
+        let view = View::new(vec![
+            (
+                0.0,
+                Row::new(vec![(
+                    0.0,
+                    Button {
+                        size: Size { width: 1.0, height: 1.0 },
+                        ..make_button("foo".into())
+                    },
+                )]),
+            ),
+        ]);
+        let layout = LayoutData {
+            keymaps: Vec::new(),
+            composition: None,
+            kind: ArrangementKind::Base,
+            margins: Margins {
+                top: 0.0,
+                left: 0.0,
+                right: 0.0,
+                bottom: 0.0,
+            },
+            views: hashmap! {
+                String::new() => (c::Point { x: 0.0, y: 0.0 }, view),
+            },
+            purpose: ContentPurpose::Normal,
+        };
+        let panel = Size { width: 100.0, height: 75.0 };
+        let transformation = layout.calculate_docked_transformation(
+            panel.clone(),
+            false,
+            Some(Side::Right),
+        );
+        assert_eq!(transformation.origin_x, 25.0);
+        assert_eq!(transformation.origin_y, 0.0);
+        assert_eq!(transformation.scale_x, 75.0);
+
+        let transformation = layout.calculate_docked_transformation(
+            panel,
+            false,
+            Some(Side::Left),
+        );
+        assert_eq!(transformation.origin_x, 0.0);
+        assert_eq!(transformation.scale_x, 75.0);
+    }
+
+    /// Presses land on buttons of the narrowed layout,
+    /// and on the side switch next to it.
+    #[test]
+    fn one_handed_press() {
+        let button = |name: &str| Button {
+            size: Size { width: 1.0, height: 1.0 },
+            ..make_button(name.into())
+        };
+        let view = View::new(vec![
+            (0.0, Row::new(vec![(0.0, button("a")), (1.0, button("b"))])),
+        ]);
+        let layout = Layout::new(
+            LayoutParseData {
+                views: hashmap! {
+                    "base".into() => (c::Point { x: 0.0, y: 0.0 }, view),
+                },
+                keymaps: Vec::new(),
+                margins: Margins {
+                    top: 0.0,
+                    left: 0.0,
+                    right: 0.0,
+                    bottom: 0.0,
+                },
+                composition: None,
+            },
+            ArrangementKind::Base,
+            ContentPurpose::Normal,
+        );
+        let panel = Size { width: 100.0, height: 25.0 };
+        let transformation = layout.shape.calculate_docked_transformation(
+            panel.clone(),
+            false,
+            Some(Side::Right),
+        );
+        // The layout takes 50 pixels, centered within the right 75.
+        assert_eq!(transformation.origin_x, 37.5);
+
+        let press = |x| transformation.forward(c::Point { x, y: 12.5 });
+        assert_eq!(layout.find_index_by_position(press(50.0)), Some((0, 0)));
+        assert_eq!(layout.find_index_by_position(press(75.0)), Some((0, 1)));
+        assert_eq!(layout.find_index_by_position(press(10.0)), None);
+        assert_eq!(layout.find_index_by_position(press(90.0)), None);
+
+        let switch = SideSwitch::new(Side::Right, &panel, &transformation);
+        assert!(switch.bounds.contains(&press(10.0)));
+        assert!(!switch.bounds.contains(&press(50.0)));
+        assert!(!switch.bounds.contains(&press(90.0)));
+        assert_eq!(switch.side.opposite(), Side::Left);
+    }
+
     #[test]
     fn check_stretching() {
         // TODO: This should work correctly independent from the current settings on the system.
@@ -1655,6 +2430,7 @@ mod test {
         ]);
         let layout = LayoutData {
             keymaps: Vec::new(),
+            composition: None,
             kind: ArrangementKind::Base,
             margins: Margins {
                 top: 0.0,
@@ -1670,7 +2446,8 @@ mod test {
         // Test that layouts will keep their defined proportions,
         // if those fit the panel precisely.
         let transformation = layout.calculate_transformation(
-            Size { width: 100.0, height: 100.0 }
+            Size { width: 100.0, height: 100.0 },
+            None,
         );
         assert_eq!(transformation.scale_x, 100.0,
             "Layout-width changed when it was supposed to not change.");
@@ -1680,7 +2457,8 @@ mod test {
         // if layout-stretching is off.
         if stretch_layout_to_fit_panel == false {
             let transformation = layout.calculate_transformation(
-                Size { width: 95.0, height: 100.0 }
+                Size { width: 95.0, height: 100.0 },
+                None,
             );
             assert_eq!(transformation.scale_x, 95.0,
                 "Layout-width did not decrease by the expected amount.");
@@ -1690,14 +2468,16 @@ mod test {
         // Test that layouts adjust to the panel-size, if layout-stretching is on.
         if stretch_layout_to_fit_panel == true {
             let transformation = layout.calculate_transformation(
-                Size { width: 500.0, height: 100.0 }
+                Size { width: 500.0, height: 100.0 },
+                None,
             );
             assert_eq!(transformation.scale_x, 500.0,
                 "Layout-width did not increase by the expected amount.");
             assert_eq!(transformation.scale_y, 100.0,
                 "Layout-height changed when it was supposed to not change.");
             let transformation = layout.calculate_transformation(
-                Size { width: 100.0, height: 204.0 }
+                Size { width: 100.0, height: 204.0 },
+                None,
             );
             assert_eq!(transformation.scale_x, 100.0,
                 "Layout-width changed when it was supposed to not change.");
@@ -1706,14 +2486,16 @@ mod test {
             // Test that layouts will keep their defined proportions,
             // if those cannot reach the borders of the screen without stretching by more than the limit.
             let transformation = layout.calculate_transformation(
-                Size { width: 501.0, height: 100.0 }
+                Size { width: 501.0, height: 100.0 },
+                None,
             );
             assert_eq!(transformation.scale_x, 100.0,
                 "Layout-width changed when it was supposed to not change.");
             assert_eq!(transformation.scale_y, 100.0,
                 "Layout-height changed when it was supposed to not change.");
             let transformation = layout.calculate_transformation(
-                Size { width: 100.0, height: 205.0 }
+                Size { width: 100.0, height: 205.0 },
+                None,
             );
             assert_eq!(transformation.scale_x, 100.0,
                 "Layout-width changed when it was supposed to not change.");
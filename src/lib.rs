@@ -25,6 +25,7 @@ mod logging;
 mod action;
 pub mod actors;
 mod animation;
+mod composition;
 pub mod data;
 mod drawing;
 mod event_loop;
@@ -35,15 +36,19 @@ pub mod keycodes;
 mod layout;
 mod locale;
 mod main;
+mod output_policy;
 mod outputs;
 mod panel;
 mod popover;
+mod punctuation;
 mod receiver;
+pub mod recording;
 pub mod resources;
 mod state;
 mod style;
 mod submission;
 pub mod tests;
+mod unicode_input;
 pub mod util;
 mod vkeyboard;
 mod xdg;
@@ -7,6 +7,7 @@
  */
 
 use std::cmp;
+use std::env;
 use std::ffi::{ CStr, CString };
 use std::fmt;
 use std::os::raw::c_char;
@@ -91,6 +92,29 @@ impl Drop for XkbInfo {
 #[derive(Clone, Debug, PartialEq)]
 pub struct OwnedTranslation(pub String);
 
+/// Locales for messages, most preferred first, like "pt_BR.UTF-8".
+/// Follows gettext: `LANGUAGE` goes first,
+/// unless the locale is "C".
+pub fn get_message_locales() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty());
+    match locale {
+        None => Vec::new(),
+        Some(locale) if locale == "C" || locale == "POSIX" => Vec::new(),
+        Some(locale) => env::var("LANGUAGE").ok()
+            .into_iter()
+            .flat_map(|languages| {
+                languages.split(':')
+                    .filter(|language| !language.is_empty())
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .chain(Some(locale))
+            .collect(),
+    }
+}
+
 fn cstring_safe(s: &str) -> CString {
     CString::new(s)
         .unwrap_or(CString::new("").unwrap())
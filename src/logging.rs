@@ -32,9 +32,30 @@
  *   but this may be a solved problem from the area of functional programming.
  * 
  * This library generally aims at the approach in 3.
+ *
+ * * * *
+ *
+ * Where the messages go is configured globally.
+ * The `SQUEEKBOARD_LOG` environment variable holds the filter,
+ * like `info,layout=debug,data::loading=warning`:
+ * the most verbose level to show, followed by overrides for modules.
+ * The module is derived from the source file where the message comes from,
+ * so messages passed to a `Handler` directly use the default.
+ *
+ * Messages go to the systemd journal when running under systemd,
+ * and to the terminal otherwise.
+ * Both the filter and the output can be changed at runtime,
+ * through the debug D-Bus interface.
  * */
 
+use std::env;
+use std::fmt;
 use std::fmt::Display;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::panic::Location;
+use std::sync::{OnceLock, RwLock};
+use std::time::Instant;
 
 /// Levels are not in order.
 pub enum Level {
@@ -80,6 +101,32 @@ impl Level {
             Level::Debug => "Debug",
         }
     }
+
+    /// Levels are not in order, but filtering needs an order anyway.
+    fn get_verbosity(&self) -> u8 {
+        match self {
+            Level::Panic => 1,
+            Level::Bug => 2,
+            Level::Error => 3,
+            Level::Warning => 4,
+            Level::Surprise => 5,
+            Level::Info => 6,
+            Level::Debug => 7,
+        }
+    }
+
+    /// Syslog priority, as used by the journal
+    fn get_priority(&self) -> u8 {
+        match self {
+            Level::Panic => 2, // critical
+            Level::Bug => 3, // error
+            Level::Error => 3,
+            Level::Warning => 4, // warning
+            Level::Surprise => 5, // notice
+            Level::Info => 6, // informational
+            Level::Debug => 7, // debug
+        }
+    }
 }
 
 impl From<Problem> for Level {
@@ -116,16 +163,18 @@ macro_rules! log_print {
 }
 
 /// Approach 2
+#[track_caller]
 pub fn print(level: Level, message: &str) {
-    Print{}.handle(level, message)
+    PrintFrom(Location::caller()).handle(level, message)
 }
 
 /// Sugar for logging errors in results.
 pub trait Warn where Self: Sized {
     type Value;
     /// Approach 2.
+    #[track_caller]
     fn or_print(self, level: Problem, message: &str) -> Option<Self::Value> {
-        self.or_warn(&mut Print {}, level.into(), message)
+        self.or_warn(&mut PrintFrom(Location::caller()), level, message)
     }
     /// Approach 3.
     fn or_warn<H: Handler>(
@@ -173,15 +222,22 @@ pub trait Handler {
     fn handle(&mut self, level: Level, message: &str);
 }
 
-/// Prints info to stdout, everything else to stderr
+/// Sends to the configured output, filtering with the default level.
 pub struct Print;
 
 impl Handler for Print {
     fn handle(&mut self, level: Level, message: &str) {
-        match level {
-            Level::Info => println!("Info: {}", message),
-            l => eprintln!("{}: {}", l.as_str(), message),
-        }
+        log(None, level, message)
+    }
+}
+
+/// Sends to the configured output,
+/// filtering with the level for the module of the source file.
+struct PrintFrom(&'static Location<'static>);
+
+impl Handler for PrintFrom {
+    fn handle(&mut self, level: Level, message: &str) {
+        log(Some(self.0.file()), level, message)
     }
 }
 
@@ -199,3 +255,294 @@ impl Handler for ProblemPanic {
         }
     }
 }
+
+/// Names of the levels in the filter.
+/// There's nothing more verbose than debug, but "trace" is a common name for it.
+const VERBOSITY_NAMES: &[(&str, u8)] = &[
+    ("off", 0),
+    ("panic", 1),
+    ("bug", 2),
+    ("error", 3),
+    ("warning", 4),
+    ("surprise", 5),
+    ("info", 6),
+    ("debug", 7),
+    ("trace", 7),
+];
+
+fn parse_verbosity(name: &str) -> Result<u8, FilterError> {
+    VERBOSITY_NAMES.iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name.trim()))
+        .map(|(_, verbosity)| *verbosity)
+        .ok_or_else(|| FilterError(name.into()))
+}
+
+fn get_verbosity_name(verbosity: u8) -> &'static str {
+    VERBOSITY_NAMES.iter()
+        .find(|(_, v)| *v == verbosity)
+        .map(|(name, _)| *name)
+        .unwrap_or("debug")
+}
+
+/// The level name which didn't make sense
+#[derive(Debug)]
+pub struct FilterError(String);
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown log level: {}", self.0)
+    }
+}
+
+/// Decides which messages get through
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    default: u8,
+    /// Module paths with their levels, like `data::loading`
+    modules: Vec<(String, u8)>,
+}
+
+impl Filter {
+    /// Everything gets through
+    fn all() -> Self {
+        Filter { default: 7, modules: Vec::new() }
+    }
+
+    /// Parses `level,module=level,...`. All parts are optional.
+    pub fn parse(spec: &str) -> Result<Self, FilterError> {
+        let mut filter = Filter::all();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part.split_once('=') {
+                Some((module, level)) => filter.modules.push(
+                    (module.trim().into(), parse_verbosity(level)?)
+                ),
+                None => filter.default = parse_verbosity(part)?,
+            }
+        }
+        Ok(filter)
+    }
+
+    /// The most specific module entry wins.
+    fn allows(&self, module: Option<&str>, level: &Level) -> bool {
+        let threshold = module
+            .and_then(|module| {
+                self.modules.iter()
+                    .filter(|(prefix, _)| {
+                        module == prefix
+                            || module.starts_with(&format!("{}::", prefix))
+                    })
+                    .max_by_key(|(prefix, _)| prefix.len())
+            })
+            .map(|(_, verbosity)| *verbosity)
+            .unwrap_or(self.default);
+        level.get_verbosity() <= threshold
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", get_verbosity_name(self.default))?;
+        for (module, verbosity) in &self.modules {
+            write!(f, ",{}={}", module, get_verbosity_name(*verbosity))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    /// Info to stdout, everything else to stderr, with timestamps
+    Terminal,
+    /// The native systemd protocol, with priorities
+    Journal,
+}
+
+impl Output {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "terminal" => Some(Output::Terminal),
+            "journal" => Some(Output::Journal),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Output::Terminal => "terminal",
+            Output::Journal => "journal",
+        }
+    }
+}
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+struct Config {
+    filter: Filter,
+    output: Output,
+    /// Created when first needed
+    journal: OnceLock<io::Result<UnixDatagram>>,
+    start: Instant,
+}
+
+fn get_config() -> &'static RwLock<Config> {
+    static CONFIG: OnceLock<RwLock<Config>> = OnceLock::new();
+    CONFIG.get_or_init(|| {
+        let (filter, error) = match env::var("SQUEEKBOARD_LOG") {
+            Ok(spec) => match Filter::parse(&spec) {
+                Ok(filter) => (filter, None),
+                Err(e) => (Filter::all(), Some(e)),
+            },
+            Err(_) => (Filter::all(), None),
+        };
+        if let Some(e) = error {
+            // The config is not available yet to log through.
+            eprintln!("Warning: Ignoring SQUEEKBOARD_LOG: {}", e);
+        }
+        // systemd sets this for the services whose output it collects.
+        let output = match env::var_os("JOURNAL_STREAM") {
+            Some(_) => Output::Journal,
+            None => Output::Terminal,
+        };
+        RwLock::new(Config {
+            filter,
+            output,
+            journal: OnceLock::new(),
+            start: Instant::now(),
+        })
+    })
+}
+
+pub fn get_filter() -> Filter {
+    get_config().read().unwrap().filter.clone()
+}
+
+pub fn set_filter(filter: Filter) {
+    get_config().write().unwrap().filter = filter;
+}
+
+pub fn get_output() -> Output {
+    get_config().read().unwrap().output
+}
+
+pub fn set_output(output: Output) {
+    get_config().write().unwrap().output = output;
+}
+
+/// Turns `src/data/loading.rs` into `data::loading`.
+fn get_module(file: &str) -> String {
+    let path = match file.rfind("src/") {
+        Some(idx) => &file[idx + 4..],
+        None => file,
+    };
+    let path = path.strip_suffix(".rs").unwrap_or(path);
+    let path = path.strip_suffix("/mod").unwrap_or(path);
+    path.replace('/', "::")
+}
+
+/// Encodes a field of the native journal protocol.
+fn push_journal_field(buffer: &mut Vec<u8>, name: &str, value: &str) {
+    buffer.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        // Multi-line values need their size spelled out.
+        buffer.push(b'\n');
+        buffer.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buffer.push(b'=');
+    }
+    buffer.extend_from_slice(value.as_bytes());
+    buffer.push(b'\n');
+}
+
+fn send_to_journal(
+    config: &Config,
+    file: Option<&str>,
+    level: &Level,
+    message: &str,
+) -> io::Result<()> {
+    let socket = config.journal
+        .get_or_init(UnixDatagram::unbound)
+        .as_ref()
+        .map_err(|e| io::Error::new(e.kind(), e.to_string()))?;
+    let mut buffer = Vec::new();
+    push_journal_field(&mut buffer, "PRIORITY", &level.get_priority().to_string());
+    push_journal_field(&mut buffer, "SYSLOG_IDENTIFIER", "squeekboard");
+    push_journal_field(&mut buffer, "SQUEEKBOARD_LEVEL", level.as_str());
+    if let Some(file) = file {
+        push_journal_field(&mut buffer, "CODE_FILE", file);
+    }
+    push_journal_field(&mut buffer, "MESSAGE", message);
+    socket.send_to(&buffer, JOURNAL_SOCKET).map(|_| ())
+}
+
+fn print_to_terminal(config: &Config, level: &Level, message: &str) {
+    let elapsed = config.start.elapsed();
+    let timestamp = format!(
+        "[{:5}.{:03}]",
+        elapsed.as_secs(),
+        elapsed.subsec_millis(),
+    );
+    match level {
+        Level::Info => println!("{} Info: {}", timestamp, message),
+        l => eprintln!("{} {}: {}", timestamp, l.as_str(), message),
+    }
+}
+
+fn log(file: Option<&str>, level: Level, message: &str) {
+    let config = get_config().read().unwrap();
+    let module = file.map(get_module);
+    if !config.filter.allows(module.as_deref(), &level) {
+        return;
+    }
+    match config.output {
+        Output::Terminal => print_to_terminal(&config, &level, message),
+        Output::Journal => {
+            if send_to_journal(&config, file, &level, message).is_err() {
+                // Nowhere else to report that.
+                print_to_terminal(&config, &level, message);
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn module_from_file() {
+        assert_eq!(get_module("src/layout.rs"), "layout");
+        assert_eq!(get_module("src/data/loading.rs"), "data::loading");
+        assert_eq!(get_module("/build/src/actors/external/mod.rs"), "actors::external");
+    }
+
+    #[test]
+    fn filter_modules() {
+        let filter = Filter::parse("warning,data=info,data::loading=debug").unwrap();
+        assert!(filter.allows(None, &Level::Warning));
+        assert!(!filter.allows(None, &Level::Info));
+        assert!(!filter.allows(Some("layout"), &Level::Info));
+        assert!(filter.allows(Some("data::parsing"), &Level::Info));
+        assert!(!filter.allows(Some("data::parsing"), &Level::Debug));
+        assert!(filter.allows(Some("data::loading"), &Level::Debug));
+        // Only whole modules match
+        assert!(!filter.allows(Some("database"), &Level::Info));
+    }
+
+    #[test]
+    fn filter_round_trip() {
+        let filter = Filter::parse("info,layout=trace").unwrap();
+        assert_eq!(filter.to_string(), "info,layout=debug");
+        assert_eq!(Filter::parse(&filter.to_string()).unwrap(), filter);
+        assert!(Filter::parse("layout=loud").is_err());
+    }
+
+    #[test]
+    fn journal_multiline() {
+        let mut buffer = Vec::new();
+        push_journal_field(&mut buffer, "MESSAGE", "a\nb");
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(buffer, expected);
+    }
+}
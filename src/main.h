@@ -2,12 +2,12 @@
 /// This all wraps https://gtk-rs.org/gtk-rs-core/stable/latest/docs/glib/struct.MainContext.html#method.channel
 
 #include <inttypes.h>
+#include <stdbool.h>
 
 #include "input-method-unstable-v2-client-protocol.h"
 #include "virtual-keyboard-unstable-v1-client-protocol.h"
 
 #include "eek/eek-types.h"
-#include "dbus.h"
 #include "panel.h"
 #include "src/popover.h"
 
@@ -27,7 +27,7 @@ struct rsobjects {
     struct squeek_popover *popover;
 };
 
-void register_ui_loop_handler(struct receiver *receiver, struct panel_manager *panel, struct squeek_popover *popover, EekboardContextService *hint_manager, DBusHandler *dbus_handler);
+void register_ui_loop_handler(struct receiver *receiver, struct panel_manager *panel, struct squeek_popover *popover, EekboardContextService *hint_manager, struct squeek_state_manager *state_manager, struct submission *submission, bool force_show);
 
 struct rsobjects squeek_init(void);
 
@@ -5,9 +5,11 @@
 /*! Glue for the main loop. */
 use crate::actors;
 use crate::actors::external::debug;
+use crate::actors::external::osk;
 use crate::animation;
 use crate::data::loading;
 use crate::event_loop;
+use crate::layout;
 use crate::panel;
 use crate::state;
 use glib::{ControlFlow, MainContext, Priority, Receiver};
@@ -24,18 +26,17 @@ mod c {
     use crate::actors::Destination;
     use crate::actors::popover;
     use crate::event_loop::driver;
+    use crate::imservice;
     use crate::imservice::IMService;
     use crate::imservice::c::InputMethod;
     use crate::layout;
     use crate::outputs::Outputs;
     use crate::state;
     use crate::submission::Submission;
+    use crate::util::CloneOwned;
     use crate::util::c::{ArcWrapped, Wrapped};
+    use crate::vkeyboard::VirtualKeyboard;
     use crate::vkeyboard::c::ZwpVirtualKeyboardV1;
-    
-    /// DbusHandler*
-    #[repr(transparent)]
-    pub struct DBusHandler(*const c_void);
 
     /// EekboardContextService* in the role of a hint receiver
     // The clone/copy is a concession to C style of programming.
@@ -66,6 +67,7 @@ mod c {
         input_method_manager: *const c_void,
         outputs: Wrapped<Outputs>,
         seat: *const c_void,
+        fractional_scale_manager: *const c_void,
         input_method: InputMethod,
         virtual_keyboard: ZwpVirtualKeyboardV1,
     }
@@ -78,6 +80,7 @@ mod c {
                 input_method_manager: ptr::null(),
                 outputs: Wrapped::new(outputs_manager),
                 seat: ptr::null(),
+                fractional_scale_manager: ptr::null(),
                 input_method: InputMethod::null(),
                 virtual_keyboard: ZwpVirtualKeyboardV1::null(),
             }
@@ -89,9 +92,7 @@ mod c {
         fn init_wayland(wayland: *mut Wayland);
         #[allow(improper_ctypes)]
         fn eekboard_context_service_set_layout(service: HintManager, name: *const c_char, layout: *const layout::Layout, timestamp: u32);
-        // This should probably only get called from the gtk main loop,
-        // given that dbus handler is using glib.
-        fn dbus_handler_set_visible(dbus: *const DBusHandler, visible: u8);
+        fn eekboard_context_service_refresh_layout(service: HintManager);
     }
     
     // INITIALIZATION
@@ -105,9 +106,15 @@ mod c {
         // Set up channels
         let (sender, receiver) = MainContext::channel(Priority::default());
         let now = Instant::now();
-        let state_manager = driver::Threaded::new(sender, state::Application::new(now));
+        let (monitor, debug_reports) = debug::Monitor::new();
+        let state_manager = driver::Threaded::new(
+            sender,
+            state::Application::new(now),
+            monitor,
+        );
 
-        debug::init(state_manager.clone());
+        debug::init(state_manager.clone(), debug_reports)
+            .or_print(logging::Problem::Warning, "Can't serve the debug interface");
 
         let outputs = Outputs::new(state_manager.clone());
         let mut wayland = Box::new(Wayland::new(outputs));
@@ -119,9 +126,14 @@ mod c {
         let imservice = if wayland.input_method.is_null() {
             None
         } else {
-            Some(IMService::new(wayland.input_method, state_manager.clone()))
+            let imservice: Box<dyn imservice::Sink>
+                = IMService::new(wayland.input_method, state_manager.clone());
+            Some(imservice)
         };
-        let submission = Submission::new(vk, imservice);
+        let submission = Submission::new(
+            Box::new(VirtualKeyboard(vk)),
+            imservice,
+        );
         
         let popover = ArcWrapped::new(actors::popover::State::new(true));
 
@@ -145,12 +157,38 @@ mod c {
         panel_manager: panel::c::PanelManager,
         popover: actors::popover::c::Actor,
         hint_manager: HintManager,
-        dbus_handler: *const DBusHandler,
+        state_manager: Wrapped<EventLoop>,
+        submission: Wrapped<Submission>,
+        // Keep running even if another instance is present
+        force_show: bool,
     ) {
         let receiver = unsafe { receiver.unwrap() };
         let receiver = Rc::try_unwrap(receiver).expect("References still present");
         let receiver = receiver.into_inner();
-        let panel_manager = Wrapped::new(panel::Manager::new(panel_manager));
+        let panel_manager = Wrapped::new(panel::Manager::new(
+            panel_manager,
+            state_manager.clone_owned(),
+        ));
+        let settings = watch_settings(state_manager.clone_owned());
+        let osk = match osk::init(state_manager.clone_owned()) {
+            Ok(osk) => Some(osk),
+            Err(osk::Error::NameTaken) => {
+                if !force_show {
+                    log_print!(
+                        logging::Level::Error,
+                        "{} is taken. Is Squeekboard already running?",
+                        osk::NAME,
+                    );
+                    std::process::exit(1);
+                }
+                None
+            },
+            Err(osk::Error::DBus(e)) => {
+                log_print!(logging::Level::Warning, "Can't serve D-Bus interface: {}", e);
+                None
+            },
+        };
+        let state_manager = state_manager.clone_owned();
         let ctx = MainContext::default();
         let _acqu = ctx.acquire();
         receiver.attach(
@@ -161,7 +199,10 @@ mod c {
                     panel_manager.clone(),
                     &popover.clone_ref(),
                     hint_manager,
-                    dbus_handler,
+                    osk.as_ref(),
+                    &settings,
+                    &submission,
+                    &state_manager,
                 );
                 ControlFlow::Continue
             },
@@ -177,19 +218,26 @@ mod c {
         panel_manager: Wrapped<panel::Manager>,
         popover: &actors::popover::Destination,
         hint_manager: HintManager,
-        dbus_handler: *const DBusHandler,
+        osk: Option<&osk::Handle>,
+        settings: &gio::Settings,
+        submission: &Wrapped<Submission>,
+        state_manager: &EventLoop,
     ) {
         if let Some(visibility) = msg.panel_visibility {
             panel::Manager::update(panel_manager, visibility);
         }
 
-        if let Some(visible) = msg.dbus_visible_set {
-            if dbus_handler != std::ptr::null() {
-                unsafe { dbus_handler_set_visible(dbus_handler, visible as u8) };
+        if let Some(osk) = osk {
+            if let Some(visible) = msg.dbus_visible_set {
+                osk.set_visible(visible);
+            }
+            if let Some(height) = msg.dbus_height_set {
+                osk.set_height(height);
             }
         }
         
         if let Some(commands::SetLayout { description }) = msg.layout_selection {
+            let contents = description.clone();
             let animation::Contents {
                 name,
                 kind,
@@ -197,10 +245,23 @@ mod c {
                 purpose,
             } = description;
             popover.send(popover::Event::Overlay(overlay_name.clone()));
-            let layout = loading::load_layout(&name, kind, purpose, &overlay_name);
+            let (layout, source)
+                = loading::load_layout_with_source(&name, kind, purpose, &overlay_name);
+            // The panel size depends on the layout,
+            // which may have changed since it was last loaded.
+            state_manager.send(Event::LayoutLoaded {
+                contents,
+                proportions: layout.shape.get_proportions(),
+            }).or_print(logging::Problem::Warning, "Can't send to state manager");
+            let name = overlay_name.unwrap_or(name);
+            debug::set_layout(name.clone(), source, &layout);
             let layout = Box::into_raw(Box::new(layout));
+            if let Some(osk) = osk {
+                osk.set_current_layout(name.clone());
+                osk.update_available_layouts();
+            }
             // CSS can't express "+" in the class
-            let name = overlay_name.unwrap_or(name).replace('+', "_");
+            let name = name.replace('+', "_");
             let name = CString::new(name).unwrap_or(
                 CString::new("").unwrap()
             );
@@ -210,6 +271,22 @@ mod c {
                 eekboard_context_service_set_layout(hint_manager, name, layout, 0);
             }
         }
+
+        if let Some(one_handed) = msg.one_handed {
+            layout::set_one_handed(one_handed);
+            unsafe {
+                eekboard_context_service_refresh_layout(hint_manager);
+            }
+        }
+
+        if let Some(enabled) = msg.smart_punctuation {
+            submission.clone_ref().borrow_mut().set_smart_punctuation(enabled);
+        }
+
+        if let Some((orientation, scale)) = msg.save_scale {
+            settings.set_double(orientation.get_scale_setting_name(), scale)
+                .or_print(logging::Problem::Warning, "Can't save the panel scale");
+        }
     }
     
     // EVENT PASSING    
@@ -221,6 +298,58 @@ mod c {
     use crate::util;
     
     use crate::logging::Warn;
+    use gio::prelude::SettingsExt;
+
+    fn get_visibility_policy(settings: &gio::Settings) -> visibility::Policy {
+        let value = settings.string("visibility-policy");
+        visibility::Policy::from_setting(value.as_str())
+            .or_print(
+                logging::Problem::Warning,
+                &format!("Unknown visibility policy: {}", value),
+            )
+            .unwrap_or(visibility::Policy::Auto)
+    }
+
+    /// The name of the output, if the user chose one.
+    fn get_pinned_output(settings: &gio::Settings) -> Option<String> {
+        Some(String::from(settings.string("output")))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Sends the settings which the state depends on,
+    /// now and whenever they change.
+    fn watch_settings(sender: EventLoop) -> gio::Settings {
+        let send = move |event| {
+            sender.send(event)
+                .or_print(logging::Problem::Warning, "Can't send to state manager");
+        };
+        let settings = gio::Settings::new("sm.puri.Squeekboard");
+        send(Event::VisibilityPolicy(get_visibility_policy(&settings)));
+        send(Event::PanelPlacement(panel::Placement::from_settings(&settings)));
+        send(Event::ReduceMotion(settings.boolean("reduce-motion")));
+        send(Event::PinnedOutput(get_pinned_output(&settings)));
+        send(Event::PanelScales(panel::Scales::from_settings(&settings)));
+        send(Event::OneHanded(layout::Side::from_settings(&settings)));
+        send(Event::SmartPunctuation(settings.boolean("smart-punctuation")));
+        settings.connect_changed(None, move |settings, key| match key {
+            "visibility-policy"
+                => send(Event::VisibilityPolicy(get_visibility_policy(settings))),
+            "anchor" | "overlay"
+                => send(Event::PanelPlacement(panel::Placement::from_settings(settings))),
+            "reduce-motion"
+                => send(Event::ReduceMotion(settings.boolean("reduce-motion"))),
+            "output" => send(Event::PinnedOutput(get_pinned_output(settings))),
+            "scale-in-vertical-screen-orientation"
+                | "scale-in-horizontal-screen-orientation"
+                => send(Event::PanelScales(panel::Scales::from_settings(settings))),
+            "one-handed" | "one-handed-side"
+                => send(Event::OneHanded(layout::Side::from_settings(settings))),
+            "smart-punctuation"
+                => send(Event::SmartPunctuation(settings.boolean("smart-punctuation"))),
+            _ => {},
+        });
+        settings
+    }
     
     #[no_mangle]
     pub extern "C"
@@ -291,9 +420,15 @@ pub mod commands {
 
 /// The commands consumed by the main loop,
 /// to be sent out to external components.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Commands {
     pub panel_visibility: Option<panel::Command>,
     pub dbus_visible_set: Option<bool>,
+    pub dbus_height_set: Option<u32>,
     pub layout_selection: Option<commands::SetLayout>,
+    /// The scale for the orientation, to write to the settings
+    pub save_scale: Option<(panel::Orientation, f64)>,
+    /// Where to dock the layout, when it changed
+    pub one_handed: Option<Option<layout::Side>>,
+    pub smart_punctuation: Option<bool>,
 }
@@ -0,0 +1,154 @@
+/* Copyright (C) 2022 Purism SPC
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/*! Choosing the output on which the panel appears.
+ *
+ * The keyboard is only useful on a touch screen,
+ * but Wayland doesn't tell which outputs take touch input.
+ * Instead, outputs get ranked:
+ *
+ * 1. the one pinned by the user, by name,
+ * 2. built-in panels, recognized by the name of the connector,
+ * 3. smaller ones, which are more likely to be held in hand.
+ *
+ * The current output stays if no other ranks better,
+ * so that the panel doesn't jump between equal outputs.
+ */
+
+use std::collections::HashMap;
+
+use crate::float_ord::FloatOrd;
+use crate::outputs::{ OutputId, OutputState };
+
+/// Connectors used by screens built into the device
+const BUILT_IN_PREFIXES: &[&str] = &["DSI-", "eDP-", "LVDS-"];
+
+/// Lower is better
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Rank {
+    not_pinned: bool,
+    not_built_in: bool,
+    /// Infinite when unknown
+    diagonal: FloatOrd<f64>,
+}
+
+fn is_built_in(name: &str) -> bool {
+    BUILT_IN_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// Physical diagonal in millimeters
+fn get_diagonal(output: &OutputState) -> Option<f64> {
+    let size = output.get_physical_size()?;
+    let width = size.width?.0 as f64;
+    let height = size.height?.0 as f64;
+    Some((width * width + height * height).sqrt())
+}
+
+fn get_rank(output: &OutputState, pinned: Option<&str>) -> Rank {
+    let name = output.name.as_deref();
+    Rank {
+        not_pinned: pinned.is_none() || name != pinned,
+        not_built_in: !name.map(is_built_in).unwrap_or(false),
+        diagonal: FloatOrd(get_diagonal(output).unwrap_or(f64::INFINITY)),
+    }
+}
+
+/// Returns the best output, preferring the `current` one among equals.
+/// `pinned` is the name of the output chosen by the user.
+pub fn choose(
+    outputs: &HashMap<OutputId, OutputState>,
+    pinned: Option<&str>,
+    current: Option<OutputId>,
+) -> Option<OutputId> {
+    let best = outputs.iter()
+        // Names are unique, so they make the choice predictable.
+        .min_by_key(|(_id, output)| (get_rank(output, pinned), output.name.clone()))
+        .map(|(id, output)| (*id, get_rank(output, pinned)));
+
+    let current = current.and_then(|id| {
+        outputs.get(&id).map(|output| (id, get_rank(output, pinned)))
+    });
+
+    match (best, current) {
+        (Some((_, best_rank)), Some((current, current_rank)))
+            if current_rank == best_rank
+        => Some(current),
+        (best, _) => best.map(|(id, _rank)| id),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::outputs::{ c, Geometry, Millimeter, Mode, Size };
+
+    fn fake_output_id(id: usize) -> OutputId {
+        OutputId(unsafe {
+            std::mem::transmute::<_, c::WlOutput>(id)
+        })
+    }
+
+    fn make_output(name: &str, width: i32, height: i32) -> OutputState {
+        OutputState {
+            current_mode: Some(Mode { width: 1000, height: 1000 }),
+            geometry: Some(Geometry {
+                transform: c::Transform::Normal,
+                phys_size: Size {
+                    width: Some(Millimeter(width)),
+                    height: Some(Millimeter(height)),
+                },
+            }),
+            scale: 1,
+            name: Some(name.into()),
+        }
+    }
+
+    #[test]
+    fn docked() {
+        let phone = fake_output_id(1);
+        let monitor = fake_output_id(2);
+        let outputs = hashmap!{
+            monitor => make_output("HDMI-A-1", 600, 340),
+            phone => make_output("DSI-1", 65, 130),
+        };
+        assert_eq!(choose(&outputs, None, None), Some(phone));
+        assert_eq!(choose(&outputs, None, Some(monitor)), Some(phone));
+        assert_eq!(choose(&outputs, Some("HDMI-A-1"), Some(phone)), Some(monitor));
+        // Pinned output is missing
+        assert_eq!(choose(&outputs, Some("DP-2"), None), Some(phone));
+    }
+
+    #[test]
+    fn smaller() {
+        let small = fake_output_id(1);
+        let large = fake_output_id(2);
+        let outputs = hashmap!{
+            large => make_output("HDMI-A-1", 600, 340),
+            small => make_output("HDMI-A-2", 300, 170),
+        };
+        assert_eq!(choose(&outputs, None, None), Some(small));
+    }
+
+    #[test]
+    fn keep_current() {
+        let first = fake_output_id(1);
+        let second = fake_output_id(2);
+        let outputs = hashmap!{
+            first => make_output("HDMI-A-1", 600, 340),
+            second => make_output("HDMI-A-2", 600, 340),
+        };
+        assert_eq!(choose(&outputs, None, None), Some(first));
+        assert_eq!(choose(&outputs, None, Some(second)), Some(second));
+    }
+
+    #[test]
+    fn removed() {
+        let first = fake_output_id(1);
+        let outputs = hashmap!{
+            first => make_output("HDMI-A-1", 600, 340),
+        };
+        assert_eq!(choose(&outputs, None, Some(fake_output_id(2))), Some(first));
+        assert_eq!(choose(&HashMap::new(), None, Some(first)), None);
+    }
+}
@@ -9,6 +9,7 @@ use std::vec::Vec;
 use crate::logging;
 use crate::main;
 use crate::util::DivCeil;
+use serde::{Deserialize, Serialize};
 
 // traits
 use crate::logging::Warn;
@@ -17,6 +18,8 @@ use crate::logging::Warn;
 pub mod c {
     use super::*;
     
+    use serde::{Deserializer, Serializer};
+    use std::ffi::CStr;
     use std::os::raw::{ c_char, c_void };
     use std::ptr;
 
@@ -39,6 +42,19 @@ pub mod c {
         }
     }
 
+    /// Recordings only need the pointer to tell outputs apart.
+    impl Serialize for WlOutput {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            (self.0 as usize).serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for WlOutput {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            usize::deserialize(deserializer).map(|address| WlOutput(address as *const c_void))
+        }
+    }
+
     #[repr(C)]
     struct WlOutputListener<T: COpaquePtr> {
         geometry: extern fn(
@@ -70,6 +86,17 @@ pub mod c {
             WlOutput,
             i32, // factor
         ),
+        // Since version 4
+        name: extern fn(
+            T, // data
+            WlOutput,
+            *const c_char, // name
+        ),
+        description: extern fn(
+            T, // data
+            WlOutput,
+            *const c_char, // description
+        ),
     }
     
     bitflags!{
@@ -82,7 +109,7 @@ pub mod c {
     }
 
     /// Map to `wl_output.transform` values
-    #[derive(Clone, Copy, Debug)]
+    #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
     pub enum Transform {
         Normal = 0,
         Rotated90 = 1,
@@ -217,7 +244,7 @@ pub mod c {
                 output.current = output.pending.clone();
                 Some(Event {
                     output: OutputId(wl_output),
-                    change: ChangeType::Altered(output.current),
+                    change: ChangeType::Altered(output.current.clone()),
                 })
             },
             None => {
@@ -253,6 +280,40 @@ pub mod c {
         };
     }
 
+    extern fn outputs_handle_name(
+        outputs: COutputs,
+        wl_output: WlOutput,
+        name: *const c_char,
+    ) {
+        let name = unsafe { CStr::from_ptr(name) }
+            .to_str()
+            .or_print(
+                logging::Problem::Warning,
+                "Received invalid wl_output.name",
+            )
+            .map(String::from);
+
+        let outputs = outputs.clone_ref();
+        let mut collection = outputs.borrow_mut();
+        let output_state: Option<&mut OutputState>
+            = collection
+                .find_output_mut(wl_output)
+                .map(|o| &mut o.pending);
+        match output_state {
+            Some(state) => { state.name = name; }
+            None => log_print!(
+                logging::Level::Warning,
+                "Got name on unknown output",
+            ),
+        };
+    }
+
+    extern fn outputs_handle_description(
+        _outputs: COutputs,
+        _wl_output: WlOutput,
+        _description: *const c_char,
+    ) {}
+
     // End callbacks
 
     #[no_mangle]
@@ -282,6 +343,8 @@ pub mod c {
                 mode: outputs_handle_mode,
                 done: outputs_handle_done,
                 scale: outputs_handle_scale,
+                name: outputs_handle_name,
+                description: outputs_handle_description,
             } as *const WlOutputListener<COutputs>,
             raw_collection,
         )};
@@ -307,7 +370,7 @@ pub mod c {
 
 
 /// Generic size
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Size<Unit> {
     pub width: Unit,
     pub height: Unit,
@@ -316,13 +379,13 @@ pub struct Size<Unit> {
 pub type PixelSize = Size<u32>;
 
 /// wl_output mode
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Mode {
     pub width: i32,
     pub height: i32,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
 pub struct Millimeter(pub i32);
 
 impl DivCeil<i32> for Millimeter {
@@ -340,17 +403,19 @@ impl ops::Mul<i32> for Millimeter {
 }
 
 /// All geometry parameters
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Geometry {
     pub transform: c::Transform,
     pub phys_size: Size<Option<Millimeter>>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct OutputState {
     pub current_mode: Option<Mode>,
     pub geometry: Option<Geometry>,
     pub scale: i32,
+    /// Connector name, like "DSI-1"
+    pub name: Option<String>,
 }
 
 impl OutputState {
@@ -365,6 +430,7 @@ impl OutputState {
             current_mode: None,
             geometry: None,
             scale: 1,
+            name: None,
         }
     }
 
@@ -420,7 +486,7 @@ impl OutputState {
 
 /// Not guaranteed to exist,
 /// but can be used to look up state.
-#[derive(Clone, Copy, PartialEq, Debug, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Debug, Eq, Hash, Serialize, Deserialize)]
 pub struct OutputId(pub c::WlOutput);
 
 // WlOutput is a pointer,
@@ -486,14 +552,14 @@ impl Outputs {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ChangeType {
     /// Added or changed
     Altered(OutputState),
     Removed,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Event {
     pub output: OutputId,
     pub change: ChangeType,
@@ -41,6 +41,47 @@ on_surface_configure(struct squeek_panel_manager *self, PhoshLayerSurface *surfa
     squeek_panel_manager_configured(self, width, height);
 }
 
+/// Calls back into Rust
+void squeek_panel_manager_scale(struct squeek_panel_manager *mgr, uint32_t scale);
+
+static void
+handle_preferred_scale(void *data, struct wp_fractional_scale_v1 *fractional_scale, uint32_t scale)
+{
+    (void)fractional_scale;
+    struct panel_manager *self = data;
+    squeek_panel_manager_scale(self->manager, scale);
+}
+
+static const struct wp_fractional_scale_v1_listener fractional_scale_listener = {
+    .preferred_scale = handle_preferred_scale,
+};
+
+static void
+on_map (struct panel_manager *self, GtkWidget *widget)
+{
+    g_assert (widget == GTK_WIDGET(self->window));
+    if (!squeek_wayland->fractional_scale_manager || self->fractional_scale) {
+        return;
+    }
+    // The surface exists only after mapping.
+    self->fractional_scale = wp_fractional_scale_manager_v1_get_fractional_scale(
+        squeek_wayland->fractional_scale_manager,
+        phosh_layer_surface_get_wl_surface(self->window)
+    );
+    wp_fractional_scale_v1_add_listener(self->fractional_scale,
+                                        &fractional_scale_listener, self);
+}
+
+static void
+on_unmap (struct panel_manager *self, GtkWidget *widget)
+{
+    g_assert (widget == GTK_WIDGET(self->window));
+    if (self->fractional_scale) {
+        wp_fractional_scale_v1_destroy(self->fractional_scale);
+        self->fractional_scale = NULL;
+    }
+}
+
 static void
 make_widget (struct panel_manager *self)
 {
@@ -56,38 +97,46 @@ make_widget (struct panel_manager *self)
 
 
 // Called also from rust
-/// Updates the size
+/// Updates the size.
+/// While sliding, the exclusive zone stays at `full_height`,
+/// so that other surfaces get resized only once.
 void
-panel_manager_resize (struct panel_manager *self, uint32_t height)
+panel_manager_resize (struct panel_manager *self, uint32_t height, uint32_t full_height)
 {
     phosh_layer_surface_set_size(self->window, 0, height);
-    phosh_layer_surface_set_exclusive_zone(self->window, height);
+    phosh_layer_surface_set_exclusive_zone(self->window, self->exclusive ? full_height : 0);
     phosh_layer_surface_wl_surface_commit(self->window);
 }
 
 // Called from rust
 /// Creates a new panel widget
 void
-panel_manager_request_widget (struct panel_manager *self, struct wl_output *output, uint32_t height, struct squeek_panel_manager *mgr)
+panel_manager_request_widget (struct panel_manager *self, struct wl_output *output, uint32_t height, uint32_t full_height, enum panel_anchor anchor, bool exclusive, struct squeek_panel_manager *mgr)
 {
+    uint32_t edges = ZWLR_LAYER_SURFACE_V1_ANCHOR_LEFT | ZWLR_LAYER_SURFACE_V1_ANCHOR_RIGHT
+        | (anchor == PANEL_ANCHOR_TOP
+           ? ZWLR_LAYER_SURFACE_V1_ANCHOR_TOP
+           : ZWLR_LAYER_SURFACE_V1_ANCHOR_BOTTOM);
+    self->manager = mgr;
+    self->exclusive = exclusive;
     if (!self->window) {
         self->window = g_object_new (
             PHOSH_TYPE_LAYER_SURFACE,
             "layer-shell", squeek_wayland->layer_shell,
             "wl-output", output,
             "height", height,
-            "anchor", ZWLR_LAYER_SURFACE_V1_ANCHOR_BOTTOM
-            | ZWLR_LAYER_SURFACE_V1_ANCHOR_LEFT
-            | ZWLR_LAYER_SURFACE_V1_ANCHOR_RIGHT,
+            "anchor", edges,
             "layer", ZWLR_LAYER_SHELL_V1_LAYER_OVERLAY,
             "kbd-interactivity", FALSE,
-            "exclusive-zone", height,
+            "exclusive-zone", exclusive ? full_height : 0,
             "namespace", "osk",
             NULL
         );
         g_object_connect (self->window,
             "swapped-signal::destroy", G_CALLBACK(on_destroy), self,
             "swapped-signal::configured", G_CALLBACK(on_surface_configure), mgr,
+            "swapped-signal-after::map", G_CALLBACK(on_map), self,
+            "swapped-signal::unmap", G_CALLBACK(on_unmap), self,
             NULL);
         // The properties below are just to make hacking easier.
         // The way we use layer-shell overrides some,
@@ -100,7 +149,10 @@ panel_manager_request_widget (struct panel_manager *self, struct wl_output *outp
         gtk_window_set_icon_name (GTK_WINDOW(self->window), "squeekboard");
         gtk_window_set_keep_above (GTK_WINDOW(self->window), TRUE);
     } else {
-        panel_manager_resize(self, height);
+        // The anchor takes effect when the window gets mapped again,
+        // which is after hiding.
+        g_object_set (G_OBJECT(self->window), "anchor", edges, NULL);
+        panel_manager_resize(self, height, full_height);
     }
 
     if (!self->widget) {
@@ -117,7 +169,10 @@ struct panel_manager panel_manager_new(EekboardContextService *state, struct sub
         .submission = submission,
         .window = NULL,
         .widget = NULL,
+        .fractional_scale = NULL,
+        .manager = NULL,
         .current_output = NULL,
+        .exclusive = true,
         .state_manager = state_manager,
         .popover = popover,
     };
@@ -1,10 +1,18 @@
 #pragma once
 
+#include <stdbool.h>
+
 #include "eek/layersurface.h"
 #include "src/layout.h"
 #include "src/main.h"
 #include "src/submission.h"
 
+/// Corresponds to panel::Anchor
+enum panel_anchor {
+    PANEL_ANCHOR_BOTTOM = 0,
+    PANEL_ANCHOR_TOP = 1,
+};
+
 // Stores the objects that the panel and its widget will refer to
 struct panel_manager {
     EekboardContextService *state; // unowned
@@ -17,9 +25,16 @@ struct panel_manager {
     // https://gitlab.gnome.org/World/Phosh/squeekboard/-/issues/343
     PhoshLayerSurface *window;
     GtkWidget *widget;
+    /// Present while the window is mapped,
+    /// if the compositor supports fractional scaling.
+    struct wp_fractional_scale_v1 *fractional_scale;
+    /// panel::Manager, receiving the preferred scale
+    struct squeek_panel_manager *manager;
 
     // Those should be held in Rust
     struct wl_output *current_output;
+    /// Whether the panel reserves its space, for resizing
+    bool exclusive;
 };
 
 struct panel_manager panel_manager_new(EekboardContextService *state, struct submission *submission, struct squeek_state_manager *state_manager, struct squeek_popover *popover);
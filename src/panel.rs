@@ -26,11 +26,23 @@
  *
  * Tight coupling, e.g. a future one between presented hints and layout size,
  * will have to be taken into account later.
+ *
+ * The allocated size is reported to `state::State` after the fact,
+ * so that it can adjust the layout to it,
+ * but the synchronization with Wayland stays here.
  */
 
 use crate::logging;
 use crate::outputs::OutputId;
+use crate::receiver;
+use crate::state;
 use crate::util::c::Wrapped;
+use gio::Settings;
+use serde::{Deserialize, Serialize};
+
+// Traits
+use crate::logging::Warn;
+use gdk::prelude::SettingsExt;
 
 
 pub mod c {
@@ -51,10 +63,13 @@ pub mod c {
             service: PanelManager,
             output: WlOutput,
             height: u32,
+            full_height: u32,
+            anchor: Anchor,
+            exclusive: bool,
             // for callbacks
             panel: Wrapped<Manager>,
         );
-        pub fn panel_manager_resize(service: PanelManager, height: u32);
+        pub fn panel_manager_resize(service: PanelManager, height: u32, full_height: u32);
         pub fn panel_manager_hide(service: PanelManager);
     }
 
@@ -69,14 +84,148 @@ pub mod c {
             glib::ControlFlow::Break
         });
     }
+
+    /// The compositor supports fractional scaling,
+    /// and sent the scale preferred for the panel, in 120ths.
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_panel_manager_scale(panel: Wrapped<Manager>, scale: u32) {
+        if scale == 0 {
+            // Sizes get divided by the scale.
+            log_print!(
+                logging::Level::Warning,
+                "Compositor preferred a scale of 0. Ignoring",
+            );
+            return;
+        }
+        glib::idle_add_local(move || {
+            let panel = panel.clone_ref();
+            panel.borrow_mut().set_scale(Scale(scale));
+            glib::ControlFlow::Break
+        });
+    }
+}
+
+
+/// The edge of the screen where the panel is docked.
+/// Corresponds to panel.h::panel_anchor.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Anchor {
+    Bottom = 0,
+    Top = 1,
+}
+
+/// Where the panel goes on the output
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Placement {
+    pub anchor: Anchor,
+    /// Reserves the space of the panel,
+    /// moving application windows out of the way.
+    /// Otherwise, the panel covers them.
+    pub exclusive: bool,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement {
+            anchor: Anchor::Bottom,
+            exclusive: true,
+        }
+    }
+}
+
+impl Placement {
+    pub fn from_settings(gsettings: &Settings) -> Self {
+        Placement {
+            anchor: match gsettings.string("anchor").as_str() {
+                "top" => Anchor::Top,
+                _ => Anchor::Bottom,
+            },
+            exclusive: !gsettings.boolean("overlay"),
+        }
+    }
+}
+
+/// The orientation of the screen, which has its own panel scale
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Orientation {
+    Vertical,
+    Horizontal,
+}
+
+impl Orientation {
+    pub fn get_scale_setting_name(self) -> &'static str {
+        match self {
+            Orientation::Vertical => "scale-in-vertical-screen-orientation",
+            Orientation::Horizontal => "scale-in-horizontal-screen-orientation",
+        }
+    }
+}
+
+/// Multipliers of the panel height chosen by the user
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Scales {
+    pub vertical: f64,
+    pub horizontal: f64,
+}
+
+impl Default for Scales {
+    fn default() -> Self {
+        Scales {
+            vertical: 1.0,
+            horizontal: 1.0,
+        }
+    }
+}
+
+impl Scales {
+    pub fn from_settings(gsettings: &Settings) -> Self {
+        Scales {
+            vertical: gsettings.double(Orientation::Vertical.get_scale_setting_name()),
+            horizontal: gsettings.double(Orientation::Horizontal.get_scale_setting_name()),
+        }
+    }
+
+    pub fn get(&self, orientation: Orientation) -> f64 {
+        match orientation {
+            Orientation::Vertical => self.vertical,
+            Orientation::Horizontal => self.horizontal,
+        }
+    }
+
+    pub fn with(self, orientation: Orientation, scale: f64) -> Self {
+        match orientation {
+            Orientation::Vertical => Scales { vertical: scale, ..self },
+            Orientation::Horizontal => Scales { horizontal: scale, ..self },
+        }
+    }
 }
 
+/// A scale factor which may be fractional.
+/// Stored in 120ths, like in the fractional scale protocol.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Scale(pub u32);
+
+impl Scale {
+    const DENOMINATOR: u32 = 120;
+
+    /// As sent with `wl_output`
+    pub fn from_integer(factor: u32) -> Self {
+        Scale(factor * Self::DENOMINATOR)
+    }
+
+    /// Physical pixels covered by the scaled (logical) size
+    pub fn to_pixels(&self, scaled: u32) -> u32 {
+        scaled * self.0 / Self::DENOMINATOR
+    }
+}
 
 /// Size in pixels that is aware of scaling
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
 pub struct PixelSize {
     pub pixels: u32,
-    pub scale_factor: u32,
+    pub scale_factor: Scale,
 }
 
 fn div_ceil(a: u32, b: u32) -> u32 {
@@ -86,11 +235,11 @@ fn div_ceil(a: u32, b: u32) -> u32 {
 
 impl PixelSize {
     pub fn as_scaled_floor(&self) -> u32 {
-        self.pixels / self.scale_factor
+        self.pixels * Scale::DENOMINATOR / self.scale_factor.0
     }
 
     pub fn as_scaled_ceiling(&self) -> u32 {
-        div_ceil(self.pixels, self.scale_factor)
+        div_ceil(self.pixels * Scale::DENOMINATOR, self.scale_factor.0)
     }
 }
 
@@ -100,6 +249,17 @@ struct Size {
     height: u32,
 }
 
+/// The size given to the panel by the compositor,
+/// in response to the request for `wanted_height`.
+/// Scaled dimensions, like those passed to Wayland.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Allocation {
+    pub output: OutputId,
+    pub wanted_height: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
 /// This state requests the Wayland layer shell protocol synchronization:
 /// the application asks for some size,
 /// and then receives a size that the compositor thought appropriate.
@@ -111,23 +271,29 @@ enum State {
         output: OutputId,
         height: u32,
         //width: u32,
+        placement: Placement,
     },
     SizeAllocated {
         output: OutputId,
         wanted_height: u32,
         allocated: Size,
+        placement: Placement,
     },
 }
 
 /// A command to send out to the next layer of processing.
 /// Here, it's the C side of the panel.
+/// The exclusive zone takes the `full_height`,
+/// so that other surfaces don't get resized while the panel slides.
 #[derive(Debug, PartialEq)]
 enum Update {
     Hide,
-    Resize { height: u32 },
+    Resize { height: u32, full_height: u32 },
     RequestWidget {
         output: OutputId,
         height: u32,
+        full_height: u32,
+        placement: Placement,
     },
 }
 
@@ -136,6 +302,9 @@ pub enum Command {
     Show {
         output: OutputId,
         height: PixelSize,
+        /// The height once the panel stops sliding
+        full_height: PixelSize,
+        placement: Placement,
     },
     Hide,
 }
@@ -143,6 +312,8 @@ pub enum Command {
 /// Tries to contain all the panel sizing duties.
 pub struct Manager {
     panel: c::PanelManager,
+    /// Receives the allocated size
+    app_state: receiver::State,
     state: State,
     // This should be part of State, if it ever actually gets unhardcoded.
     // It's here because State doesn't need to become more complex
@@ -151,15 +322,15 @@ pub struct Manager {
 }
 
 impl Manager {
-    pub fn new(panel: c::PanelManager) -> Self {
+    pub fn new(panel: c::PanelManager, app_state: receiver::State) -> Self {
         Self {
             panel,
+            app_state,
             state: State::Hidden,
             debug: false,
         }
     }
-    // TODO: mabe send the allocated size back to state::State,
-    // to perform layout adjustments
+
     fn set_configured(&mut self, size: Size) {
         if self.debug {
             eprintln!("Panel received configure {:?}", &size);
@@ -170,6 +341,36 @@ impl Manager {
         if self.debug {
             eprintln!("Panel now {:?}", &self.state);
         }
+
+        // The state may adjust the layout to the space it actually got.
+        if let State::SizeAllocated { output, wanted_height, allocated, .. } = &self.state {
+            self.app_state
+                .send(state::Event::PanelConfigured(Allocation {
+                    output: *output,
+                    wanted_height: *wanted_height,
+                    width: allocated.width,
+                    height: allocated.height,
+                }))
+                .or_print(logging::Problem::Bug, "Can't send to state");
+        }
+    }
+
+    /// The scale only matters for the output the panel is on.
+    fn set_scale(&mut self, scale: Scale) {
+        if self.debug {
+            eprintln!("Panel received scale {:?}", &scale);
+        }
+
+        let output = match &self.state {
+            State::Hidden => None,
+            State::SizeRequested { output, .. } => Some(*output),
+            State::SizeAllocated { output, .. } => Some(*output),
+        };
+        if let Some(output) = output {
+            self.app_state
+                .send(state::Event::PanelScale { output, scale })
+                .or_print(logging::Problem::Bug, "Can't send to state");
+        }
     }
 
     pub fn update(mgr: Wrapped<Manager>, cmd: Command) {
@@ -189,10 +390,18 @@ impl Manager {
             unsafe {
                 match update {
                     Update::Hide => c::panel_manager_hide(mgr.panel),
-                    Update::Resize { height }
-                        => c::panel_manager_resize(mgr.panel, *height),
-                    Update::RequestWidget{output, height}
-                        => c::panel_manager_request_widget(mgr.panel, output.0, *height, copied.clone()),
+                    Update::Resize { height, full_height }
+                        => c::panel_manager_resize(mgr.panel, *height, *full_height),
+                    Update::RequestWidget{output, height, full_height, placement}
+                        => c::panel_manager_request_widget(
+                            mgr.panel,
+                            output.0,
+                            *height,
+                            *full_height,
+                            placement.anchor,
+                            placement.exclusive,
+                            copied.clone(),
+                        ),
                 }
             }
         }
@@ -217,17 +426,18 @@ impl State {
                 );
                 State::Hidden
             },
-            State::SizeAllocated{output, wanted_height, ..} => {
+            State::SizeAllocated{output, wanted_height, placement, ..} => {
                 log_print!(
                     logging::Level::Surprise,
                     "Panel received new configuration without asking",
                 );
-                State::SizeAllocated{output, wanted_height, allocated: size}
+                State::SizeAllocated{output, wanted_height, allocated: size, placement}
             },
-            State::SizeRequested{output, height} => State::SizeAllocated {
+            State::SizeRequested{output, height, placement} => State::SizeAllocated {
                 output,
                 wanted_height: height,
                 allocated: size,
+                placement,
             },
         }
     }
@@ -241,22 +451,26 @@ impl State {
             (Command::Hide, State::SizeRequested{..}) => (
                 State::Hidden, vec![Update::Hide],
             ),
-            (Command::Show{output, height}, State::Hidden) => {
+            (Command::Show{output, height, full_height, placement}, State::Hidden) => {
                 let height = height.as_scaled_ceiling();
+                let full_height = full_height.as_scaled_ceiling();
                 (
-                    State::SizeRequested{output, height},
-                    vec![Update::RequestWidget{ output, height }],
+                    State::SizeRequested{output, height, placement},
+                    vec![Update::RequestWidget{ output, height, full_height, placement }],
                 )
             },
             (
-                Command::Show{output, height},
-                State::SizeRequested{output: req_output, height: req_height},
+                Command::Show{output, height, full_height, placement},
+                State::SizeRequested{output: req_output, height: req_height, placement: req_placement},
             ) => {
                 let height = height.as_scaled_ceiling();
-                if output == req_output && height == req_height {(
-                    State::SizeRequested{output: req_output, height: req_height},
+                let full_height = full_height.as_scaled_ceiling();
+                // The anchor can only change on a new surface.
+                let same_surface = output == req_output && placement == req_placement;
+                if same_surface && height == req_height {(
+                    State::SizeRequested{output, height, placement},
                     Vec::new(),
-                )} else if output == req_output {(
+                )} else if same_surface {(
                     // I'm not sure about that.
                     // This could cause a busy loop,
                     // when two requests are being processed at the same time:
@@ -269,40 +483,42 @@ impl State {
                     // Doing nothing means that Squeekboard will occasionally use the stale size (see test),
                     // so instead always listen to the higher layer and request a new size.
                     // If this causes problems, maybe count requests/configures, or track what was allocated in response to what request.
-                    State::SizeRequested{output, height},
-                    vec![Update::Resize { height }],
+                    State::SizeRequested{output, height, placement},
+                    vec![Update::Resize { height, full_height }],
                 )} else {(
                     // This looks weird, but should be safe.
                     // The stack seems to handle
                     // configure events on a dead surface.
-                    State::SizeRequested{output, height},
+                    State::SizeRequested{output, height, placement},
                     vec![
                         Update::Hide,
-                        Update::RequestWidget { output, height },
+                        Update::RequestWidget { output, height, full_height, placement },
                     ],
                 )}
             },
             (
-                Command::Show{output, height},
-                State::SizeAllocated{output: alloc_output, allocated, wanted_height},
+                Command::Show{output, height, full_height, placement},
+                State::SizeAllocated{output: alloc_output, allocated, wanted_height, placement: alloc_placement},
             ) => {
                 let height = height.as_scaled_ceiling();
-                if output == alloc_output && height == wanted_height {(
-                    State::SizeAllocated{output: alloc_output, wanted_height, allocated},
+                let full_height = full_height.as_scaled_ceiling();
+                let same_surface = output == alloc_output && placement == alloc_placement;
+                if same_surface && height == wanted_height {(
+                    State::SizeAllocated{output, wanted_height, allocated, placement},
                     Vec::new(),
-                )} else if output == alloc_output && height == allocated.height {(
-                    State::SizeAllocated{output: alloc_output, wanted_height: height, allocated},
+                )} else if same_surface && height == allocated.height {(
+                    State::SizeAllocated{output, wanted_height: height, allocated, placement},
                     Vec::new(),
-                )} else if output == alloc_output {(
+                )} else if same_surface {(
                     // Should *all* other heights cause a resize?
                     // What about those between wanted and allocated?
-                    State::SizeRequested{output, height},
-                    vec![Update::Resize{height}],
+                    State::SizeRequested{output, height, placement},
+                    vec![Update::Resize{height, full_height}],
                 )} else {(
-                    State::SizeRequested{output, height},
+                    State::SizeRequested{output, height, placement},
                     vec![
                         Update::Hide,
-                        Update::RequestWidget{output, height},
+                        Update::RequestWidget{output, height, full_height, placement},
                     ]
                    )}
             },
@@ -315,6 +531,44 @@ mod tests {
     use super::*;
     use crate::outputs::c::WlOutput;
     
+    /// Moving the panel to the top needs a new surface.
+    #[test]
+    fn change_anchor() {
+        #[allow(non_upper_case_globals)]
+        const output: OutputId = OutputId(WlOutput::dummy());
+        let height = PixelSize { pixels: 100, scale_factor: Scale::from_integer(1) };
+        let top = Placement { anchor: Anchor::Top, exclusive: true };
+
+        let state = State::SizeAllocated {
+            output,
+            wanted_height: 100,
+            allocated: Size { width: 50, height: 100 },
+            placement: Placement::default(),
+        };
+        let (state, cmds) = state.update(
+            Command::Show { output, height, full_height: height, placement: top }
+        );
+        assert_eq!(
+            cmds,
+            vec![
+                Update::Hide,
+                Update::RequestWidget { output, height: 100, full_height: 100, placement: top },
+            ],
+        );
+        let (_state, cmds) = state.update(
+            Command::Show { output, height, full_height: height, placement: top }
+        );
+        assert_eq!(cmds, Vec::new());
+    }
+
+    #[test]
+    fn fractional() {
+        let height = PixelSize { pixels: 631, scale_factor: Scale(180) };
+        assert_eq!(height.as_scaled_floor(), 420);
+        assert_eq!(height.as_scaled_ceiling(), 421);
+        assert_eq!(Scale(180).to_pixels(420), 630);
+    }
+
     #[test]
     fn resize_before_configured() {
         // allow to make typing fields easier
@@ -326,22 +580,31 @@ mod tests {
         // Initial show
         let (state, cmds) = state.update(Command::Show {
             output,
-            height: PixelSize { pixels: 100, scale_factor: 1 },
+            height: PixelSize { pixels: 100, scale_factor: Scale::from_integer(1) },
+            full_height: PixelSize { pixels: 100, scale_factor: Scale::from_integer(1) },
+            placement: Placement::default(),
         });
         assert_eq!(
             cmds,
-            vec![Update::RequestWidget { output, height: 100 }],
+            vec![Update::RequestWidget {
+                output,
+                height: 100,
+                full_height: 100,
+                placement: Placement::default(),
+            }],
         );
         // layer shell requests a resize
 
         // but another show comes before first can be confirmed
         let (state, cmds) = dbg!(state).update(Command::Show {
             output,
-            height: PixelSize { pixels: 50, scale_factor: 1 },
+            height: PixelSize { pixels: 50, scale_factor: Scale::from_integer(1) },
+            full_height: PixelSize { pixels: 50, scale_factor: Scale::from_integer(1) },
+            placement: Placement::default(),
         });
         assert_eq!(
             cmds,
-            vec![Update::Resize { height: 50 }],
+            vec![Update::Resize { height: 50, full_height: 50 }],
             "{:?}",
             state,
         );
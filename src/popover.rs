@@ -5,12 +5,15 @@ use gtk;
 use std::ffi::CString;
 use std::cmp::Ordering;
 use crate::actors;
+use crate::data::loading;
+use crate::data::parsing::Meta;
 use crate::layout::c::{ Bounds, EekGtkKeyboard };
-use crate::locale::{ OwnedTranslation, compare_current_locale };
+use crate::locale::{ OwnedTranslation, compare_current_locale, get_message_locales };
 use crate::logging;
 use crate::receiver;
 use crate::resources;
 use crate::state;
+use serde::{Deserialize, Serialize};
 
 // Traits
 use gio::prelude::ActionMapExt;
@@ -147,7 +150,7 @@ fn set_layout(kind: &str, name: &str) {
 }
 
 /// A reference to what the user wants to see
-#[derive(PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum LayoutId {
     /// Affects the layout in system settings
     System {
@@ -167,7 +170,41 @@ impl LayoutId {
     }
 }
 
-fn set_visible_layout(
+/// Layouts from the system settings, the current one first
+fn get_system_layouts() -> Vec<LayoutId> {
+    let settings = get_settings("org.gnome.desktop.input-sources");
+    let inputs = settings
+        .map(|settings| {
+            let inputs = settings.value("sources");
+
+            variants::get_tuples(inputs)
+        })
+        .unwrap_or_else(|| Vec::new());
+
+    inputs.into_iter()
+        .map(|(kind, name)| LayoutId::System { kind, name })
+        .collect()
+}
+
+/// Builtin overlays come first
+fn get_overlay_layouts() -> impl Iterator<Item=LayoutId> {
+    resources::get_overlays().into_iter()
+        .map(String::from)
+        .chain(loading::get_custom_overlays())
+        .map(LayoutId::Local)
+}
+
+/// All the layouts which the user can choose from,
+/// in the same order as in the popover: system ones first.
+pub fn get_available_layouts() -> Vec<LayoutId> {
+    get_system_layouts().into_iter()
+        .chain(get_overlay_layouts())
+        .collect()
+}
+
+/// Makes the system layout the current one in the system settings.
+/// Local layouts don't change the settings.
+pub fn set_visible_layout(
     layout_id: &LayoutId,
 ) {
     match layout_id {
@@ -189,9 +226,17 @@ fn get_current_layout(
     }
 }
 
+/// The layout's own description, if it has one
+pub fn get_meta(layout: &LayoutId) -> Option<Meta> {
+    match layout {
+        LayoutId::System { kind: _, name } => loading::get_meta(name),
+        LayoutId::Local(name) => loading::get_overlay_meta(name),
+    }
+}
+
 /// Translates all provided layout names according to current locale,
 /// for the purpose of display (i.e. errors will be caught and reported)
-fn translate_layout_names(layouts: &Vec<LayoutId>) -> Vec<OwnedTranslation> {
+pub fn translate_layout_names(layouts: &Vec<LayoutId>) -> Vec<OwnedTranslation> {
     // `XkbInfo` being temporary means that its return values must be
     // copied, forcing the use of `OwnedTranslation`.
     enum Status {
@@ -201,20 +246,28 @@ fn translate_layout_names(layouts: &Vec<LayoutId>) -> Vec<OwnedTranslation> {
         Remaining(String),
     }
 
-    // Attempt to take all xkb names from gnome-desktop's xkb info.
+    let locales = get_message_locales();
+    // Names from the layout itself come first, even if xkb knows better.
+    // Otherwise, attempt to take xkb names from gnome-desktop's xkb info.
     let xkb_translator = crate::locale::XkbInfo::new();
 
     let translated_names = layouts.iter()
-        .map(|id| match id {
-            LayoutId::System { name, kind: _ } => {
-                xkb_translator.get_display_name(name)
-                    .map(|s| Status::Translated(OwnedTranslation(s)))
-                    .or_print(
-                        logging::Problem::Surprise,
-                        &format!("No display name for xkb layout {}", name),
-                    ).unwrap_or_else(|| Status::Remaining(name.clone()))
-            },
-            LayoutId::Local (_) => unreachable!(),
+        .map(|id| {
+            let meta_name = get_meta(id).and_then(|meta|
+                meta.get_name(&locales).map(String::from)
+            );
+            match (meta_name, id) {
+                (Some(name), _) => Status::Translated(OwnedTranslation(name)),
+                (None, LayoutId::System { name, kind: _ }) => {
+                    xkb_translator.get_display_name(name)
+                        .map(|s| Status::Translated(OwnedTranslation(s)))
+                        .or_print(
+                            logging::Problem::Surprise,
+                            &format!("No display name for xkb layout {}", name),
+                        ).unwrap_or_else(|| Status::Remaining(name.clone()))
+                },
+                (None, LayoutId::Local(name)) => Status::Remaining(name.clone()),
+            }
         });
 
     translated_names
@@ -225,6 +278,33 @@ fn translate_layout_names(layouts: &Vec<LayoutId>) -> Vec<OwnedTranslation> {
         .collect()
 }
 
+/// The keyboard picks up the changes by watching the settings.
+/// The side gets switched with the button next to the docked keyboard.
+fn add_one_handed_actions(
+    action_group: &gio::SimpleActionGroup,
+    settings: gio::Settings,
+) {
+    let enabled = settings.boolean("one-handed");
+
+    let one_handed_action = gio::SimpleAction::new_stateful(
+        "one-handed",
+        None,
+        &enabled.to_variant(),
+    );
+    one_handed_action.connect_change_state(move |action, state| {
+        if let Some(enabled) = state.and_then(|v| v.get::<bool>()) {
+            settings.set_boolean("one-handed", enabled)
+                .or_print(
+                    logging::Problem::Warning,
+                    "Can't change the one-handed mode",
+                );
+            action.set_state(&enabled.to_variant());
+        }
+    });
+
+    action_group.add_action(&one_handed_action);
+}
+
 pub fn show(
     window: EekGtkKeyboard,
     position: Bounds,
@@ -234,25 +314,11 @@ pub fn show(
     unsafe { gtk::set_initialized() };
     let window = unsafe { gtk::Widget::from_glib_none(window.0) };
 
-    let overlay_layouts = resources::get_overlays().into_iter()
-        .map(|name| LayoutId::Local(name.to_string()));
-
-    let settings = get_settings("org.gnome.desktop.input-sources");
-    let inputs = settings
-        .map(|settings| {
-            let inputs = settings.value("sources");
-
-            variants::get_tuples(inputs)
-        })
-        .unwrap_or_else(|| Vec::new());
-    
-    let system_layouts: Vec<LayoutId> = inputs.into_iter()
-        .map(|(kind, name)| LayoutId::System { kind, name })
-        .collect();
+    let system_layouts = get_system_layouts();
 
     let all_layouts: Vec<LayoutId> = system_layouts.clone()
         .into_iter()
-        .chain(overlay_layouts)
+        .chain(get_overlay_layouts())
         .collect();
 
     let translated_names = translate_layout_names(&system_layouts);
@@ -279,6 +345,28 @@ pub fn show(
         }
     };
 
+    // Builtin overlays are already in the menu, with translated names.
+    // Custom ones go after them.
+    let custom_overlays: Vec<LayoutId> = loading::get_custom_overlays()
+        .into_iter()
+        .map(LayoutId::Local)
+        .collect();
+    let mut custom_names: Vec<(OwnedTranslation, LayoutId)>
+        = translate_layout_names(&custom_overlays)
+            .into_iter()
+            .zip(custom_overlays.into_iter())
+            .collect();
+    custom_names.sort_unstable_by(|(tr_a, _), (tr_b, _)| {
+        compare_current_locale(&tr_a.0, &tr_b.0)
+    });
+
+    let builtin_overlay_count = resources::get_overlays().len();
+    for (index, (tr, l)) in custom_names.iter().enumerate() {
+        let detailed_action = format!("layout::{}", l.get_name());
+        let item = gio::MenuItem::new(Some(&tr.0), Some(detailed_action.as_str()));
+        model.insert_item((builtin_overlay_count + index) as i32, &item);
+    }
+
     for (tr, l) in human_names.iter().rev() {
         let detailed_action = format!("layout::{}", l.get_name());
         let item = gio::MenuItem::new(Some(&tr.0), Some(detailed_action.as_str()));
@@ -353,6 +441,10 @@ pub fn show(
     });
     action_group.add_action(&settings_action);
 
+    if let Some(settings) = get_settings("sm.puri.Squeekboard") {
+        add_one_handed_actions(&action_group, settings);
+    }
+
     menu.insert_action_group("popup", Some(&action_group));
 
     menu.bind_model(Some(&model), Some("popup"));
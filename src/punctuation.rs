@@ -0,0 +1,128 @@
+/*! Smart punctuation: adjusting spaces around punctuation as it's typed.
+ *
+ * A space before a punctuation mark gets removed,
+ * and one is added after the mark.
+ * Pressing space twice after a word ends the sentence with a period.
+ *
+ * This is a library module. It only decides what to change,
+ * based on the text before the cursor.
+ */
+
+use crate::imservice::ContentPurpose;
+
+/// Marks which get followed by a space.
+const MARKS: &[char] = &[',', '.', '!', '?'];
+
+/// Replaces text before the cursor.
+#[derive(Debug, PartialEq)]
+pub struct Edit {
+    /// Bytes to remove before the cursor
+    pub delete_before: usize,
+    pub text: String,
+}
+
+impl Edit {
+    pub fn is_empty(&self) -> bool {
+        self.delete_before == 0 && self.text.is_empty()
+    }
+}
+
+/// Where spaces matter in other ways than in prose.
+pub fn is_allowed(purpose: ContentPurpose) -> bool {
+    !matches!(
+        purpose,
+        ContentPurpose::Terminal
+            | ContentPurpose::Url
+            | ContentPurpose::Email
+            | ContentPurpose::Password
+            | ContentPurpose::Pin
+    )
+}
+
+/// Returns what to submit in place of the text, if it needs adjusting.
+/// Without any text before the cursor, nothing is adjusted,
+/// because it may be missing if the application doesn't report it.
+pub fn get_edit(before: &str, text: &str) -> Option<Edit> {
+    let mut previous = before.chars().rev();
+    let last = previous.next()?;
+    let second = previous.next();
+
+    let mut chars = text.chars();
+    let typed = match (chars.next(), chars.next()) {
+        (Some(c), None) => c,
+        _ => return None,
+    };
+
+    match (typed, second, last) {
+        // The space got added already.
+        (' ', Some(c), ' ') if MARKS.contains(&c) => Some(Edit {
+            delete_before: 0,
+            text: String::new(),
+        }),
+        (' ', Some(c), ' ') if c.is_alphanumeric() => Some(Edit {
+            delete_before: 1,
+            text: ". ".into(),
+        }),
+        (' ', _, _) => None,
+        // Numbers like 3.14 or 1,000
+        ('.', _, c) | (',', _, c) if c.is_ascii_digit() => None,
+        (mark, Some(c), ' ') if MARKS.contains(&mark) && !c.is_whitespace() => Some(Edit {
+            delete_before: 1,
+            text: format!("{} ", mark),
+        }),
+        // Spaces which are there on purpose
+        (_, _, c) if c.is_whitespace() => None,
+        (mark, _, _) if MARKS.contains(&mark) => Some(Edit {
+            delete_before: 0,
+            text: format!("{} ", mark),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn edit(delete_before: usize, text: &str) -> Option<Edit> {
+        Some(Edit { delete_before, text: text.into() })
+    }
+
+    #[test]
+    fn double_space() {
+        assert_eq!(get_edit("Hello ", " "), edit(1, ". "));
+        assert_eq!(get_edit("Hello. ", " "), edit(0, ""));
+        assert_eq!(get_edit("Hello", " "), None);
+        assert_eq!(get_edit("-- ", " "), None);
+    }
+
+    #[test]
+    fn marks() {
+        assert_eq!(get_edit("Hello ", ","), edit(1, ", "));
+        assert_eq!(get_edit("Hello", "!"), edit(0, "! "));
+        assert_eq!(get_edit("Wait. ", "."), edit(1, ". "));
+        assert_eq!(get_edit("Hello\n ", "?"), None);
+        assert_eq!(get_edit("Hello\n", "?"), None);
+        assert_eq!(get_edit("Hello", "a"), None);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(get_edit("3", "."), None);
+        assert_eq!(get_edit("1", ","), None);
+        assert_eq!(get_edit("3", "!"), edit(0, "! "));
+    }
+
+    #[test]
+    fn no_context() {
+        assert_eq!(get_edit("", "."), None);
+        assert_eq!(get_edit("", " "), None);
+    }
+
+    #[test]
+    fn purposes() {
+        assert!(is_allowed(ContentPurpose::Normal));
+        assert!(!is_allowed(ContentPurpose::Url));
+        assert!(!is_allowed(ContentPurpose::Terminal));
+    }
+}
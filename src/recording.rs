@@ -0,0 +1,234 @@
+/* Copyright (C) 2022 Purism SPC
+ * SPDX-License-Identifier: GPL-3.0-or-later
+ */
+
+/*! Recording of the events which drive the state, and their replay.
+ *
+ * The state is a pure function of the events and their times,
+ * so a recording reproduces what the user saw.
+ * Settings reach the state as events, so they get recorded too.
+ * Recording happens in the loop driver, around each state update,
+ * keeping the file out of the state.
+ *
+ * A recording is a file with one JSON entry per line.
+ * The first one holds the state when the recording started,
+ * and the following ones hold the events in the order they were applied.
+ * Times are relative to the start of the recording process,
+ * and move to the start of the replaying process when read back.
+ */
+
+use crate::event_loop;
+use crate::logging;
+use crate::state::{Application, Event};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// Traits
+use crate::event_loop::ActorState;
+use crate::logging::Warn;
+
+
+/// Stores `Instant`s as signed microseconds since the start of the process,
+/// for use with `#[serde(with)]`.
+pub mod instant {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::sync::OnceLock;
+    use std::time::{Duration, Instant};
+
+    fn get_epoch() -> Instant {
+        static EPOCH: OnceLock<Instant> = OnceLock::new();
+        *EPOCH.get_or_init(Instant::now)
+    }
+
+    pub fn to_micros(instant: Instant) -> i64 {
+        let epoch = get_epoch();
+        if instant >= epoch {
+            (instant - epoch).as_micros() as i64
+        } else {
+            -((epoch - instant).as_micros() as i64)
+        }
+    }
+
+    pub fn from_micros(micros: i64) -> Instant {
+        let epoch = get_epoch();
+        let offset = Duration::from_micros(micros.unsigned_abs());
+        if micros >= 0 {
+            epoch + offset
+        } else {
+            epoch - offset
+        }
+    }
+
+    pub fn serialize<S: Serializer>(instant: &Instant, serializer: S)
+        -> Result<S::Ok, S::Error>
+    {
+        to_micros(*instant).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D)
+        -> Result<Instant, D::Error>
+    {
+        i64::deserialize(deserializer).map(from_micros)
+    }
+}
+
+/// A line of the recording
+#[derive(Serialize, Deserialize)]
+enum Entry<S, E> {
+    Start {
+        #[serde(with = "instant")]
+        time: Instant,
+        state: S,
+    },
+    Event {
+        #[serde(with = "instant")]
+        time: Instant,
+        event: E,
+    },
+}
+
+/// Carried by events which control the recording.
+/// Those events don't get recorded,
+/// because replaying them would start recording again.
+#[derive(Clone, Debug)]
+pub enum Request {
+    Start(PathBuf),
+    Stop,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The recording doesn't begin with the state
+    MissingStart,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "IO: {}", e),
+            Error::Json(e) => write!(f, "JSON: {}", e),
+            Error::MissingStart => write!(f, "The initial state is missing"),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// Writes entries to the file.
+/// Owned by the loop driver.
+pub struct Recorder {
+    file: LineWriter<File>,
+}
+
+impl Recorder {
+    /// Creates the file, starting with the current state.
+    pub fn start<S: Serialize>(path: &Path, state: &S, now: Instant)
+        -> Result<Self, Error>
+    {
+        let mut recorder = Recorder {
+            file: LineWriter::new(File::create(path)?),
+        };
+        recorder.write(&Entry::<&S, ()>::Start { time: now, state })?;
+        Ok(recorder)
+    }
+
+    pub fn record<E: Serialize>(&mut self, event: &E, now: Instant) {
+        self.write(&Entry::<(), &E>::Event { time: now, event })
+            .or_print(logging::Problem::Warning, "Can't record event");
+    }
+
+    fn write<S: Serialize, E: Serialize>(&mut self, entry: &Entry<S, E>)
+        -> Result<(), Error>
+    {
+        serde_json::to_writer(&mut self.file, entry)?;
+        writeln!(self.file)?;
+        Ok(())
+    }
+}
+
+/// Applies the recorded events to the recorded state,
+/// printing the outcome and the commands after each one.
+pub fn replay(path: &Path) -> Result<(), Error> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let (start, state) = match lines.next() {
+        Some(line) => match serde_json::from_str::<Entry<Application, Event>>(&line?)? {
+            Entry::Start { time, state } => (time, state),
+            Entry::Event { .. } => return Err(Error::MissingStart),
+        },
+        None => return Err(Error::MissingStart),
+    };
+
+    println!("Initial outcome:\n{:#?}", state.get_outcome(start));
+    let mut loop_state = event_loop::State::new(state, start);
+    for line in lines {
+        let (time, event) = match serde_json::from_str::<Entry<Application, Event>>(&line?)? {
+            Entry::Event { time, event } => (time, event),
+            Entry::Start { .. } => continue,
+        };
+        println!(
+            "\nAt {} ms:\n{:#?}",
+            time.saturating_duration_since(start).as_millis(),
+            event,
+        );
+        let (new_state, commands) = event_loop::handle_event(loop_state, event, time);
+        loop_state = new_state;
+        println!(
+            "Outcome:\n{:#?}\nCommands:\n{:#?}",
+            loop_state.state.get_outcome(time),
+            commands,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::test::application_with_fake_output;
+    use crate::state::visibility;
+
+    #[test]
+    fn instant_round_trip() {
+        for micros in [0, 1_500_000, -1_500_000] {
+            assert_eq!(instant::to_micros(instant::from_micros(micros)), micros);
+        }
+    }
+
+    /// Outputs are keys in the state, which JSON needs to store as strings.
+    #[test]
+    fn entry_round_trip() {
+        let now = Instant::now();
+        let entries: [Entry<Application, Event>; 2] = [
+            Entry::Start {
+                time: now,
+                state: application_with_fake_output(now),
+            },
+            Entry::Event {
+                time: now,
+                event: Event::Visibility(visibility::Event::ForceVisible),
+            },
+        ];
+        for entry in entries {
+            let line = serde_json::to_string(&entry).unwrap();
+            let read: Entry<Application, Event> = serde_json::from_str(&line).unwrap();
+            assert_eq!(serde_json::to_string(&read).unwrap(), line);
+        }
+    }
+}
@@ -27,7 +27,6 @@
 
 #include "eek/eek.h"
 #include "eekboard/eekboard-context-service.h"
-#include "dbus.h"
 #include "layout.h"
 #include "main.h"
 #include "outputs.h"
@@ -51,7 +50,6 @@ typedef enum _SqueekboardDebugFlags {
 /// or secondary data structures of the same general shape.
 struct squeekboard {
     struct squeek_wayland wayland; // Just hooks.
-    DBusHandler *dbus_handler; // Controls visibility of the OSK.
     EekboardContextService *settings_context; // Gsettings hooks for layouts.
     /// Gsettings hook for visibility. TODO: this does not belong in gsettings.
     ServerContextService *settings_handler;
@@ -67,35 +65,6 @@ quit (void)
   g_main_loop_quit (loop);
 }
 
-// D-Bus
-
-static void
-on_name_acquired (GDBusConnection *connection,
-                  const gchar     *name,
-                  gpointer         user_data)
-{
-    (void)connection;
-    (void)name;
-    (void)user_data;
-}
-
-static void
-on_name_lost (GDBusConnection *connection,
-              const gchar     *name,
-              gpointer         user_data)
-{
-    SqueekboardDebugFlags *flags = user_data;
-    // TODO: could conceivable continue working
-    // if intrnal changes stop sending dbus changes
-    (void)connection;
-    (void)name;
-    (void)user_data;
-    g_warning("DBus unavailable, unclear how to continue. Is Squeekboard already running?");
-    if ((*flags & SQUEEKBOARD_DEBUG_FLAG_FORCE_SHOW) == 0) {
-        exit (1);
-    }
-}
-
 // Wayland
 
 static void
@@ -109,7 +78,6 @@ registry_handle_global (void *data,
     // so there's no reason to check for available versions.
     // Even when lower version would be served, it would not be supported,
     // causing a hard exit
-    (void)version;
     struct squeek_wayland *wayland = data;
 
     if (!strcmp (interface, zwlr_layer_shell_v1_interface.name)) {
@@ -122,12 +90,17 @@ registry_handle_global (void *data,
         wayland->input_method_manager = wl_registry_bind(registry, name,
             &zwp_input_method_manager_v2_interface, 1);
     } else if (!strcmp (interface, "wl_output")) {
+        // Version 4 brings the output name, used to choose the output.
         struct wl_output *output = wl_registry_bind (registry, name,
-            &wl_output_interface, 2);
+            &wl_output_interface,
+            MIN(version, MIN(4, (uint32_t)wl_output_interface.version)));
         squeek_outputs_register(wayland->outputs, output, name);
     } else if (!strcmp(interface, "wl_seat")) {
         wayland->seat = wl_registry_bind(registry, name,
             &wl_seat_interface, 1);
+    } else if (!strcmp(interface, wp_fractional_scale_manager_v1_interface.name)) {
+        wayland->fractional_scale_manager = wl_registry_bind(registry, name,
+            &wp_fractional_scale_manager_v1_interface, 1);
     }
 }
 
@@ -400,42 +373,6 @@ main (int argc, char **argv)
 
     instance.settings_context = eekboard_context_service_new(rsobjects.state_manager);
 
-    // set up dbus
-
-    // TODO: make dbus errors non-always-fatal
-    // dbus is not strictly necessary for the useful operation
-    // if text-input is used, as it can bring the keyboard in and out
-
-    GDBusConnection *connection = NULL;
-    connection = g_bus_get_sync (G_BUS_TYPE_SESSION, NULL, &err);
-    if (connection == NULL) {
-        g_printerr ("Can't connect to the bus: %s. "
-                    "Visibility switching unavailable.", err->message);
-    }
-    guint owner_id = 0;
-    DBusHandler *service = NULL;
-    if (connection) {
-        service = dbus_handler_new(connection, DBUS_SERVICE_PATH, rsobjects.state_manager);
-
-        if (service == NULL) {
-            g_printerr ("Can't create dbus server\n");
-            exit (1);
-        }
-        instance.dbus_handler = service;
-
-        owner_id = g_bus_own_name_on_connection (connection,
-                                                 DBUS_SERVICE_INTERFACE,
-                                                 G_BUS_NAME_OWNER_FLAGS_NONE,
-                                                 on_name_acquired,
-                                                 on_name_lost,
-                                                 &debug_flags,
-                                                 NULL);
-        if (owner_id == 0) {
-            g_printerr ("Can't own the name\n");
-            exit (1);
-        }
-    }
-
     ServerContextService *setting_listener = server_context_service_new(
                 rsobjects.state_manager);
     if (!setting_listener) {
@@ -451,7 +388,8 @@ main (int argc, char **argv)
         rsobjects.state_manager,
         rsobjects.popover);
 
-    register_ui_loop_handler(rsobjects.receiver, &instance.panel_manager, rsobjects.popover, instance.settings_context, instance.dbus_handler);
+    register_ui_loop_handler(rsobjects.receiver, &instance.panel_manager, rsobjects.popover, instance.settings_context, rsobjects.state_manager, rsobjects.submission,
+        debug_flags & SQUEEKBOARD_DEBUG_FLAG_FORCE_SHOW);
 
     session_register();
 
@@ -465,15 +403,6 @@ main (int argc, char **argv)
     loop = g_main_loop_new (NULL, FALSE);
     g_main_loop_run (loop);
 
-    if (connection) {
-        if (service) {
-            if (owner_id != 0) {
-                g_bus_unown_name (owner_id);
-            }
-            g_object_unref (service);
-        }
-        g_object_unref (connection);
-    }
     g_main_loop_unref (loop);
 
     return 0;
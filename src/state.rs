@@ -10,41 +10,46 @@ use crate::animation;
 use crate::event_loop;
 use crate::event_loop::ActorState;
 use crate::imservice::{ ContentHint, ContentPurpose };
-use crate::layout::ArrangementKind;
+use crate::layout;
+use crate::layout::{ ArrangementKind, Proportions };
 use crate::main;
 use crate::main::Commands;
+use crate::output_policy;
 use crate::outputs;
 use crate::outputs::{Millimeter, OutputId, OutputState};
 use crate::panel;
 use crate::panel::PixelSize;
 use crate::popover;
+use crate::recording;
 use crate::util::Rational;
-use gio::Settings;
-use gdk::prelude::SettingsExt;
+use serde::{Deserialize, Serialize};
 use std::cmp;
 use std::collections::HashMap;
 use std::time::Instant;
 
+/// Limits of the scale settings, as in the schema.
+const MIN_SCALE: f64 = 0.5;
+const MAX_SCALE: f64 = 2.0;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Presence {
     Present,
     Missing,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct InputMethodDetails {
     pub hint: ContentHint,
     pub purpose: ContentPurpose,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum InputMethod {
     Active(InputMethodDetails),
-    InactiveSince(Instant),
+    InactiveSince(#[serde(with = "recording::instant")] Instant),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum LayoutSource {
     Xkb,
     Other(String),
@@ -61,7 +66,7 @@ impl From<String> for LayoutSource {
 }
 
 /// The user's preferred system layout
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LayoutChoice {
     pub name: String,
     pub source: LayoutSource,
@@ -69,19 +74,45 @@ pub struct LayoutChoice {
 
 /// Incoming events.
 /// This contains events that cause a change to the internal state.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Event {
     InputMethod(InputMethod),
     Visibility(visibility::Event),
     PhysicalKeyboard(Presence),
     Output(outputs::Event),
+    /// The user chose the output for the panel by name, or left it to the policy.
+    PinnedOutput(Option<String>),
+    VisibilityPolicy(visibility::Policy),
+    /// The user doesn't want the panel to slide
+    ReduceMotion(bool),
     LayoutChoice(LayoutChoice),
     OverlayChanged(popover::LayoutId),
+    /// The UI loaded the layout, and measured it
+    LayoutLoaded {
+        contents: animation::Contents,
+        proportions: Proportions,
+    },
+    PanelResize(resize::Event),
+    /// The user chose where to dock the panel
+    PanelPlacement(panel::Placement),
+    /// The user chose how much to enlarge or shrink the panel
+    PanelScales(panel::Scales),
+    /// The user docked the layout to a side of the panel, or undocked it
+    OneHanded(Option<layout::Side>),
+    /// The user wants the spacing around punctuation to be fixed
+    SmartPunctuation(bool),
+    /// The compositor gave the panel its size
+    PanelConfigured(panel::Allocation),
+    /// The compositor prefers a fractional scale for the panel on the output
+    PanelScale {
+        output: OutputId,
+        scale: panel::Scale,
+    },
     Debug(debug::Event),
     /// Event triggered because a moment in time passed.
     /// Use to animate state transitions.
     /// The value is the ideal arrival time.
-    TimeoutReached(Instant),
+    TimeoutReached(#[serde(with = "recording::instant")] Instant),
 }
 
 impl event_loop::Event for Event {
@@ -95,6 +126,15 @@ impl event_loop::Event for Event {
             _ => None,
         }
     }
+
+    fn get_recording_request(&self) -> Option<recording::Request> {
+        match self {
+            Self::Debug(debug::Event::StartRecording(path))
+                => Some(recording::Request::Start(path.clone())),
+            Self::Debug(debug::Event::StopRecording) => Some(recording::Request::Stop),
+            _ => None,
+        }
+    }
 }
 
 impl From<InputMethod> for Event {
@@ -110,7 +150,9 @@ impl From<outputs::Event> for Event {
 }
 
 pub mod visibility {
-    #[derive(Clone, Debug)]
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
     pub enum Event {
         /// User requested the panel to show
         ForceVisible,
@@ -118,7 +160,7 @@ pub mod visibility {
         ForceHidden,
     }
 
-    #[derive(Clone, PartialEq, Debug, Copy)]
+    #[derive(Clone, PartialEq, Debug, Copy, Serialize, Deserialize)]
     pub enum State {
         /// Last interaction was user forcing the panel to go visible
         ForcedVisible,
@@ -127,13 +169,62 @@ pub mod visibility {
         /// Last interaction was the input method changing active state
         NotForced,
     }
+
+    /// When the panel shows up on its own. Chosen in gsettings.
+    #[derive(Clone, PartialEq, Debug, Copy, Serialize, Deserialize)]
+    pub enum Policy {
+        /// Shows for text input, unless a physical keyboard is present
+        Auto,
+        /// Shows for text input, even with a physical keyboard,
+        /// e.g. one which lacks some characters
+        AlwaysWhenTextInput,
+        /// Like `Auto`, but a physical keyboard also hides a forced panel
+        NeverWithPhysicalKeyboard,
+        /// Shows only when the user asks for it
+        ManualOnly,
+    }
+
+    impl Policy {
+        /// Parses the value of the setting
+        pub fn from_setting(value: &str) -> Option<Self> {
+            match value {
+                "auto" => Some(Policy::Auto),
+                "always-when-text-input" => Some(Policy::AlwaysWhenTextInput),
+                "never-with-physical-keyboard" => Some(Policy::NeverWithPhysicalKeyboard),
+                "manual-only" => Some(Policy::ManualOnly),
+                _ => None,
+            }
+        }
+    }
+}
+
+pub mod resize {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub enum Event {
+        /// The user is dragging the edge of the panel.
+        /// The offset is how far the handle moved since it was pressed,
+        /// in logical pixels, downwards positive.
+        /// It's measured within the panel, which may move while resizing.
+        Drag { offset: i32 },
+        /// The user let go of the edge
+        Done,
+    }
 }
 
 /// The outwardly visible state.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Outcome {
     pub panel: animation::Outcome,
+    /// Height which the compositor gave to the shown panel,
+    /// in logical pixels. 0 when hidden.
+    pub allocated_height: u32,
     pub im: InputMethod,
+    /// The scale chosen by resizing the panel, to be saved in the settings.
+    pub save_scale: Option<(panel::Orientation, f64)>,
+    pub one_handed: Option<layout::Side>,
+    pub smart_punctuation: bool,
 }
 
 impl event_loop::Outcome for Outcome {
@@ -146,8 +237,13 @@ impl event_loop::Outcome for Outcome {
     fn get_commands_to_reach(&self, new_state: &Self) -> Commands {
 // FIXME: handle switching outputs
         let (dbus_visible_set, panel_visibility) = match new_state.panel {
-            animation::Outcome::Visible{output, height, ..}
-                => (Some(true), Some(panel::Command::Show{output, height})),
+            animation::Outcome::Visible{output, height, full_height, placement, ..}
+                => (Some(true), Some(panel::Command::Show{
+                    output,
+                    height,
+                    full_height,
+                    placement,
+                })),
             animation::Outcome::Hidden => (Some(false), Some(panel::Command::Hide)),
         };
 
@@ -174,10 +270,20 @@ impl event_loop::Outcome for Outcome {
             animation::Outcome::Hidden => None,
         };        
 
+        // Moving the layout needs a new transformation, so only do it when needed.
+        let one_handed = match self.one_handed == new_state.one_handed {
+            true => None,
+            false => Some(new_state.one_handed),
+        };
+
         Commands {
             panel_visibility,
             dbus_visible_set,
+            dbus_height_set: Some(new_state.allocated_height),
             layout_selection,
+            save_scale: new_state.save_scale,
+            one_handed,
+            smart_punctuation: Some(new_state.smart_punctuation),
         }
     }
 }
@@ -193,18 +299,24 @@ impl event_loop::Outcome for Outcome {
 /// All state changes return the next state and the optimal time for the next check.
 ///
 /// This state tracker can be driven by any event loop.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Application {
     pub im: InputMethod,
     pub visibility_override: visibility::State,
+    pub visibility_policy: visibility::Policy,
+    /// Movement of the panel as of the last event.
+    /// Hiding after the timeout happens without an event, see `get_slide`.
+    pub slide: animation::Slide,
+    pub reduce_motion: bool,
     pub physical_keyboard: Presence,
     pub debug_mode_enabled: bool,
     /// The output on which the panel should appear.
-    /// This is stored as part of the state
-    /// because it's not clear how to derive the output from the rest of the state.
-    /// It should probably follow the focused input,
-    /// but not sure about being allowed on non-touch displays.
+    /// This is stored as part of the state,
+    /// because the policy prefers to keep the current output.
+    /// See `output_policy`.
     pub preferred_output: Option<OutputId>,
+    /// The name of the output chosen by the user
+    pub pinned_output: Option<String>,
     pub outputs: HashMap<OutputId, OutputState>,
     /// We presume that the system always has some preference,
     /// even though we receive the preference after init,
@@ -213,6 +325,27 @@ pub struct Application {
     pub layout_choice: LayoutChoice,
     /// Manual override of the system layout
     pub overlay_layout: Option<popover::LayoutId>,
+    /// Scale chosen while the user is resizing the panel.
+    /// It takes precedence over the setting until the resizing is done.
+    pub panel_scale: Option<f64>,
+    /// Height of the panel when the resizing started, in logical pixels
+    pub resizing_from: Option<u32>,
+    /// Chosen in the settings for each orientation
+    pub panel_scales: panel::Scales,
+    /// The scale which the user chose by resizing the panel.
+    /// Present only until the next event, so that it gets saved once.
+    pub scale_to_save: Option<(panel::Orientation, f64)>,
+    /// The last size given to the panel by the compositor
+    pub panel_allocation: Option<panel::Allocation>,
+    pub panel_placement: panel::Placement,
+    /// Scales preferred by the compositor, when it supports fractional scaling.
+    /// They take precedence over the integer scales of the outputs.
+    pub fractional_scales: HashMap<OutputId, panel::Scale>,
+    /// Side of the panel where the layout is docked, if any
+    pub one_handed: Option<layout::Side>,
+    pub smart_punctuation: bool,
+    /// The layouts measured when they were last loaded
+    pub layout_proportions: Vec<(animation::Contents, Proportions)>,
 }
 
 impl Application {
@@ -226,15 +359,30 @@ impl Application {
         Self {
             im: InputMethod::InactiveSince(now),
             visibility_override: visibility::State::NotForced,
+            visibility_policy: visibility::Policy::Auto,
+            slide: animation::Slide::hidden(now),
+            // No animations until the setting is known.
+            reduce_motion: true,
             physical_keyboard: Presence::Missing,
             debug_mode_enabled: false,
             preferred_output: None,
+            pinned_output: None,
             outputs: Default::default(),
             layout_choice: LayoutChoice {
                 name: String::from("us"),
                 source: LayoutSource::Xkb,
             },
             overlay_layout: None,
+            panel_scale: None,
+            resizing_from: None,
+            panel_scales: Default::default(),
+            scale_to_save: None,
+            panel_allocation: None,
+            panel_placement: Default::default(),
+            fractional_scales: Default::default(),
+            one_handed: None,
+            smart_punctuation: false,
+            layout_proportions: Vec::new(),
         }
     }
 
@@ -246,15 +394,24 @@ impl Application {
                 event,
             );
         }
+        // Takes into account the hiding which happened since the last event.
+        let slide = self.get_slide(now);
+        let resize_done = matches!(event, Event::PanelResize(resize::Event::Done));
         let state = match event {
-            Event::Debug(dbg) => Self {
-                debug_mode_enabled: match dbg {
-                    debug::Event::Enable => true,
-                    debug::Event::Disable => false,
-                },
+            Event::Debug(debug::Event::Enable) => Self {
+                debug_mode_enabled: true,
+                ..self
+            },
+
+            Event::Debug(debug::Event::Disable) => Self {
+                debug_mode_enabled: false,
                 ..self
             },
 
+            // Recording is done by the loop driver.
+            Event::Debug(debug::Event::StartRecording(_))
+                | Event::Debug(debug::Event::StopRecording) => self,
+
             Event::TimeoutReached(_) => self,
 
             Event::Visibility(visibility) => Self {
@@ -265,27 +422,38 @@ impl Application {
                 ..self
             },
 
+            Event::VisibilityPolicy(visibility_policy) => Self {
+                visibility_policy,
+                ..self
+            },
+
+            Event::ReduceMotion(reduce_motion) => Self {
+                reduce_motion,
+                ..self
+            },
+
             Event::PhysicalKeyboard(presence) => Self {
                 physical_keyboard: presence,
                 ..self
             },
 
+            Event::PinnedOutput(pinned_output) => Self {
+                pinned_output,
+                ..self
+            },
+
             Event::Output(outputs::Event { output, change }) => {
                 let mut app = self;
                 match change {
                     outputs::ChangeType::Altered(state) => {
                         app.outputs.insert(output, state);
-                        app.preferred_output = app.preferred_output.or(Some(output));
                     },
                     outputs::ChangeType::Removed => {
                         app.outputs.remove(&output);
-                        if app.preferred_output == Some(output) {
-                            // There's currently no policy to choose one output over another,
-                            // so just take whichever comes first.
-                            app.preferred_output = app.outputs.keys().next().map(|output| *output);
-                        }
+                        app.fractional_scales.remove(&output);
                     },
                 };
+                // The output gets chosen below.
                 app
             },
 
@@ -339,6 +507,126 @@ impl Application {
                 overlay_layout: Some(overlay_layout),
                 ..self
             },
+
+            Event::LayoutLoaded { contents, proportions } => {
+                let mut layout_proportions = self.layout_proportions;
+                layout_proportions.retain(|(measured, _)| *measured != contents);
+                layout_proportions.push((contents, proportions));
+                Self {
+                    layout_proportions,
+                    ..self
+                }
+            },
+
+            Event::PanelResize(resize::Event::Drag { offset }) => {
+                let current_height = self.panel_allocation.as_ref()
+                    .map(|allocation| allocation.height);
+                let resizing_from = self.resizing_from.or(current_height);
+                // The edge opposite to the anchor follows the point.
+                let height = match self.panel_placement.anchor {
+                    // The panel moves together with the top edge,
+                    // so the offset is relative to the current height.
+                    panel::Anchor::Bottom
+                        => current_height.map(|height| height as i32 - offset),
+                    // The panel stays in place.
+                    panel::Anchor::Top
+                        => resizing_from.map(|height| height as i32 + offset),
+                }.map(|height| height.max(0) as u32);
+                Self {
+                    panel_scale: height
+                        .zip(self.preferred_output)
+                        .and_then(|(height, id)| {
+                            self.outputs.get(&id).map(|output| (height, id, output))
+                        })
+                        .and_then(|(height, id, output)| Self::get_scale_for_height(
+                            output,
+                            self.get_scale_factor(id, output),
+                            height,
+                            |kind| self.get_layout_proportions(kind),
+                        ))
+                        .or(self.panel_scale),
+                    resizing_from,
+                    ..self
+                }
+            },
+
+            Event::PanelConfigured(allocation) => {
+                if self.debug_mode_enabled && allocation.height != allocation.wanted_height {
+                    println!(
+                        "Panel got height {} instead of {}",
+                        allocation.height,
+                        allocation.wanted_height,
+                    );
+                }
+                Self {
+                    panel_allocation: Some(allocation),
+                    ..self
+                }
+            },
+
+            Event::PanelPlacement(panel_placement) => Self {
+                panel_placement,
+                ..self
+            },
+
+            Event::PanelScales(panel_scales) => Self {
+                panel_scales,
+                ..self
+            },
+
+            Event::OneHanded(one_handed) => Self {
+                one_handed,
+                ..self
+            },
+
+            Event::SmartPunctuation(smart_punctuation) => Self {
+                smart_punctuation,
+                ..self
+            },
+
+            Event::PanelScale { output, scale } => {
+                let mut app = self;
+                app.fractional_scales.insert(output, scale);
+                app
+            },
+
+            Event::PanelResize(resize::Event::Done) => {
+                let scale_to_save = self.panel_scale
+                    .zip(self.get_preferred_output_state())
+                    .map(|(scale, output)| (Self::get_orientation(output), scale));
+                Self {
+                    panel_scales: match scale_to_save {
+                        Some((orientation, scale))
+                            => self.panel_scales.with(orientation, scale),
+                        None => self.panel_scales,
+                    },
+                    panel_scale: None,
+                    resizing_from: None,
+                    scale_to_save,
+                    ..self
+                }
+            },
+        };
+
+        let state = match resize_done {
+            true => state,
+            false => Self {
+                scale_to_save: None,
+                ..state
+            },
+        };
+
+        let state = Self {
+            preferred_output: output_policy::choose(
+                &state.outputs,
+                state.pinned_output.as_deref(),
+                state.preferred_output,
+            ),
+            ..state
+        };
+        let state = Self {
+            slide: slide.turn(state.is_target_visible(now), now),
+            ..state
         };
 
         if state.debug_mode_enabled {
@@ -354,9 +642,118 @@ Outcome:
         state
     }
 
-    fn get_preferred_height_and_arrangement(output: &OutputState)
-        -> Option<(PixelSize, ArrangementKind)>
-    {
+    /// Whether the text field asks to keep its contents private.
+    pub fn is_input_sensitive(&self) -> bool {
+        match &self.im {
+            InputMethod::Active(details)
+                => details.hint.contains(ContentHint::SENSITIVE_DATA),
+            InputMethod::InactiveSince(_) => false,
+        }
+    }
+
+    /// Whether the panel should end up visible, ignoring the animation.
+    fn is_target_visible(&self, now: Instant) -> bool {
+        use visibility::Policy;
+        self.preferred_output.is_some()
+            && match (self.visibility_policy, self.physical_keyboard, self.visibility_override) {
+                (_, _, visibility::State::ForcedHidden) => false,
+                (Policy::NeverWithPhysicalKeyboard, Presence::Present, _) => false,
+                (_, _, visibility::State::ForcedVisible) => true,
+                (Policy::ManualOnly, _, visibility::State::NotForced) => false,
+                (Policy::Auto, Presence::Present, visibility::State::NotForced) => false,
+                (_, _, visibility::State::NotForced) => match self.im {
+                    InputMethod::Active(_) => true,
+                    InputMethod::InactiveSince(since) => now < since + animation::HIDING_TIMEOUT,
+                },
+            }
+    }
+
+    /// The movement of the panel at the given time.
+    /// Events update the stored slide,
+    /// but the hiding timeout passes without an event,
+    /// and then the panel starts sliding out on its own.
+    fn get_slide(&self, now: Instant) -> animation::Slide {
+        match (self.slide.shown, self.is_target_visible(now), &self.im) {
+            (true, false, InputMethod::InactiveSince(since)) => {
+                let hiding_start = cmp::max(
+                    *since + animation::HIDING_TIMEOUT,
+                    self.slide.start,
+                );
+                self.slide.turn(false, hiding_start)
+            },
+            _ => self.slide,
+        }
+    }
+
+    /// The part of the panel which is shown, from 0 to 1
+    fn get_shown_fraction(&self, now: Instant) -> f64 {
+        match (self.reduce_motion, self.is_target_visible(now)) {
+            (true, true) => 1.0,
+            (true, false) => 0.0,
+            (false, _) => self.get_slide(now).get_fraction(now),
+        }
+    }
+
+    fn get_preferred_output_state(&self) -> Option<&OutputState> {
+        self.preferred_output.and_then(|output| self.outputs.get(&output))
+    }
+
+    /// The fractional scale of the panel if known, otherwise the one of the output.
+    fn get_scale_factor(&self, id: OutputId, output: &OutputState) -> panel::Scale {
+        self.fractional_scales.get(&id)
+            .copied()
+            .unwrap_or(panel::Scale::from_integer(output.scale as u32))
+    }
+
+    /// The orientation which decides the applicable scale setting.
+    fn get_orientation(output: &OutputState) -> panel::Orientation {
+        let is_vertical = output.get_physical_size()
+            .map(|size| {
+                size.width.unwrap_or(Millimeter(60)) < size.height.unwrap_or(Millimeter(120))
+            })
+            .unwrap_or(true);
+        if is_vertical {
+            panel::Orientation::Vertical
+        } else {
+            panel::Orientation::Horizontal
+        }
+    }
+
+    /// Finds the scale which makes the panel the given height in logical pixels,
+    /// within the range allowed by the setting.
+    fn get_scale_for_height(
+        output: &OutputState,
+        scale_factor: panel::Scale,
+        height: u32,
+        get_proportions: impl Fn(ArrangementKind) -> Proportions,
+    ) -> Option<f64> {
+        let px_size = output.get_pixel_size()?;
+        let (base, _) = Self::get_preferred_height_and_arrangement(
+            output,
+            scale_factor,
+            1.0,
+            get_proportions,
+        )?;
+        if base.pixels == 0 {
+            return None;
+        }
+        let pixels = cmp::min(
+            base.scale_factor.to_pixels(height),
+            (px_size.height as f64 * (2.0 / 3.0)) as u32,
+        );
+        Some((pixels as f64 / base.pixels as f64).clamp(MIN_SCALE, MAX_SCALE))
+    }
+
+    /// `scale_factor` converts between physical and logical pixels.
+    /// `scale` is the user's multiplier of the height.
+    /// `get_proportions` describes the layout which will be shown
+    /// in the chosen arrangement.
+    fn get_preferred_height_and_arrangement(
+        output: &OutputState,
+        scale_factor: panel::Scale,
+        scale: f64,
+        get_proportions: impl Fn(ArrangementKind) -> Proportions,
+    ) -> Option<(PixelSize, ArrangementKind)> {
         output.get_pixel_size()
             .map(|px_size| {
                 // Assume isotropy.
@@ -380,42 +777,39 @@ Outcome:
                     denominator: 100, // Increase precision to 0.01 mm.
                 };
 
-                // TODO: Calculate this, based on the selected layout.
-                const ROW_COUNT: u32 = 4;
-
-                let ideal_panel_height = IDEAL_BUTTON_SIZE * ROW_COUNT as i32;
-                let ideal_panel_height_px = (ideal_panel_height * pixel_density).ceil().0 as u32;
-
                 let screen_width = output.get_physical_size().unwrap().width.unwrap_or(Millimeter(60));
                 let screen_height = output.get_physical_size().unwrap().height.unwrap_or(Millimeter(120));
                 let screen_size_short_side = cmp::min(screen_width, screen_height);
 
                 let screen_aspect_ratio = {px_size.height as f64 / px_size.width as f64};
 
-                let gsettings = Settings::new("sm.puri.Squeekboard");
-                let scale_setting_horizontal = gsettings.double("scale-in-horizontal-screen-orientation");
-                let scale_setting_vertical = gsettings.double("scale-in-vertical-screen-orientation");
+                // TODO: Check if there is a better size-range for using the compact layout.
+                let arrangement = if screen_width < screen_height
+                    && screen_size_short_side < Millimeter(115)
+                {
+                    ArrangementKind::Base
+                }
+                // Tablets in landscape are held at the sides,
+                // where thumbs don't reach the middle of the screen.
+                // Notebooks and monitors are larger, and not held.
+                else if screen_width > screen_height
+                    && screen_size_short_side >= Millimeter(120)
+                    && screen_width < Millimeter(260)
+                {
+                    ArrangementKind::Split
+                } else {
+                    ArrangementKind::Wide
+                };
 
                 // Reduce height, to match what the layout can fill.
-                // For this, we need to guess if normal or wide will be picked.
-                // This must match `eek_gtk_keyboard.c::get_type`.
-                // TODO: query layout database and choose one directly
-                let (arrangement, layout_aspect_ratio) = {
-                    // TODO: Check if there is a better size-range for using the compact layout.
-                    if screen_width < screen_height && screen_size_short_side < Millimeter(115) {(
-                        ArrangementKind::Base,
-                        Rational {
-                            numerator: 700,
-                            denominator: 1200,
-                        },
-                    )} else {(
-                        ArrangementKind::Wide,
-                        Rational {
-                            numerator: 500,
-                            denominator: 1600,
-                        },
-                    )}
-                };
+                let proportions = get_proportions(arrangement);
+                let layout_aspect_ratio = proportions.aspect_ratio;
+                let layout_aspect_ratio_f64
+                    = layout_aspect_ratio.numerator as f64
+                    / layout_aspect_ratio.denominator as f64;
+
+                let ideal_panel_height = IDEAL_BUTTON_SIZE * proportions.rows as i32;
+                let ideal_panel_height_px = (ideal_panel_height * pixel_density).ceil().0 as u32;
 
                 let recommended_panel_height
                     = cmp::min(
@@ -425,32 +819,83 @@ Outcome:
 
                       let panel_height = {
                                 if arrangement == ArrangementKind::Base && screen_width < screen_height {
-                            cmp::min((px_size.height as f64 / (screen_aspect_ratio / (7.0 / 12.0))) as u32, px_size.height / 2)}
+                            cmp::min((px_size.height as f64 / (screen_aspect_ratio / layout_aspect_ratio_f64)) as u32, px_size.height / 2)}
                            else if arrangement == ArrangementKind::Wide && screen_width < screen_height {
-                            cmp::min((px_size.height as f64 / (screen_aspect_ratio / (5.0 / 16.0))) as u32, px_size.height / 2)}
-                           else if arrangement == ArrangementKind::Wide {
+                            cmp::min((px_size.height as f64 / (screen_aspect_ratio / layout_aspect_ratio_f64)) as u32, px_size.height / 2)}
+                           else if arrangement != ArrangementKind::Base {
                             cmp::min(cmp::max(px_size.height / 3 as u32, recommended_panel_height), px_size.height / 2)}
                            else {px_size.height / 2}
                            };
 
                 (
                     PixelSize {
-                        scale_factor: output.scale as u32,
+                        scale_factor,
                         // Set the height of the panel for the layout.
-                        pixels: if screen_width < screen_height {
-                                 cmp::min((panel_height as f64 * scale_setting_vertical) as u32,
-                                        (px_size.height as f64 * (2.0 / 3.0)) as u32)
-                                        }
-                                else {
-                                 cmp::min((panel_height as f64 * scale_setting_horizontal) as u32,
-                                        (px_size.height as f64 * (2.0 / 3.0)) as u32)
-                                        },
+                        pixels: cmp::min((panel_height as f64 * scale) as u32,
+                                         (px_size.height as f64 * (2.0 / 3.0)) as u32),
                     },
                     arrangement,
                 )
             })
     }
     
+    /// The compositor may give the panel less height than requested.
+    /// Then the base layout would get squashed,
+    /// and the wide one fits better.
+    /// The requested height stays the same,
+    /// so that the allocation doesn't change in response.
+    fn fit_arrangement_to_allocation(
+        &self,
+        output: OutputId,
+        height: PixelSize,
+        arrangement: ArrangementKind,
+    ) -> ArrangementKind {
+        match (arrangement, &self.panel_allocation) {
+            (ArrangementKind::Base, Some(allocation))
+                if allocation.output == output
+                    && allocation.wanted_height == height.as_scaled_ceiling()
+                    && allocation.height * 4 < allocation.wanted_height * 3
+            => ArrangementKind::Wide,
+            _ => arrangement,
+        }
+    }
+
+    fn get_purpose(&self) -> ContentPurpose {
+        match self.im {
+            InputMethod::Active(InputMethodDetails { purpose, .. }) => purpose,
+            InputMethod::InactiveSince(_) => ContentPurpose::Normal,
+        }
+    }
+
+    /// Proportions of the layout that gets loaded for the arrangement,
+    /// as of the last time it was loaded.
+    fn get_layout_proportions(&self, kind: ArrangementKind) -> Proportions {
+        let (name, overlay_name) = self.get_layout_names();
+        let contents = animation::Contents {
+            name,
+            kind,
+            overlay_name,
+            purpose: self.get_purpose(),
+        };
+        self.layout_proportions.iter()
+            .find(|(measured, _)| *measured == contents)
+            .map(|(_, proportions)| *proportions)
+            .unwrap_or_else(|| Self::get_default_proportions(kind))
+    }
+
+    /// Until the layout gets loaded, it's assumed to be like the "us" one.
+    fn get_default_proportions(kind: ArrangementKind) -> Proportions {
+        Proportions {
+            rows: 4,
+            aspect_ratio: match kind {
+                ArrangementKind::Base => Rational { numerator: 700, denominator: 1200 },
+                ArrangementKind::Wide => Rational { numerator: 500, denominator: 1600 },
+                // The wide layout with a gap in the middle
+                ArrangementKind::Split => Rational { numerator: 500, denominator: 2080 },
+            },
+        }
+    }
+
     /// Returns layout name, overlay name
     fn get_layout_names(&self) -> (String, Option<String>) {
         (
@@ -476,54 +921,77 @@ impl ActorState for Application {
     
     fn get_outcome(&self, now: Instant) -> Outcome {
         // FIXME: include physical keyboard presence
-        Outcome {
-            panel: match self.preferred_output {
-                None => animation::Outcome::Hidden,
-                Some(output) => {
-                    let (height, arrangement) = Self::get_preferred_height_and_arrangement(self.outputs.get(&output).unwrap())
-                        .unwrap_or((
-                            PixelSize{pixels: 0, scale_factor: 1},
-                            ArrangementKind::Base,
-                        ));
-                    let (layout_name, overlay) = self.get_layout_names();
-        
-                    // TODO: Instead of setting size to 0 when the output is invalid,
-                    // simply go invisible.
-                    let visible = animation::Outcome::Visible{
+        let panel = match self.preferred_output {
+            None => animation::Outcome::Hidden,
+            Some(output) => {
+                let output_state = self.outputs.get(&output).unwrap();
+                let scale = self.panel_scale.unwrap_or(
+                    self.panel_scales.get(Self::get_orientation(output_state))
+                );
+                let (height, arrangement) = Self::get_preferred_height_and_arrangement(
+                    output_state,
+                    self.get_scale_factor(output, output_state),
+                    scale,
+                    |kind| self.get_layout_proportions(kind),
+                )
+                    .unwrap_or((
+                        PixelSize{pixels: 0, scale_factor: panel::Scale::from_integer(1)},
+                        ArrangementKind::Base,
+                    ));
+                let arrangement = self.fit_arrangement_to_allocation(output, height, arrangement);
+                let (layout_name, overlay) = self.get_layout_names();
+    
+                let fraction = self.get_shown_fraction(now);
+                let full_height = height;
+                // The height is only reduced while sliding.
+                let height = if fraction < 1.0 {
+                    PixelSize {
+                        pixels: (height.pixels as f64 * fraction).ceil() as u32,
+                        ..height
+                    }
+                } else {
+                    height
+                };
+
+                // TODO: Instead of setting size to 0 when the output is invalid,
+                // simply go invisible.
+                if fraction > 0.0 {
+                    animation::Outcome::Visible{
                         output,
                         height,
+                        full_height,
+                        placement: self.panel_placement,
                         contents: animation::Contents {
                             kind: arrangement,
                             name: layout_name,
                             overlay_name: overlay,
-                            purpose: match self.im {
-                                InputMethod::Active(InputMethodDetails { purpose, .. }) => purpose,
-                                InputMethod::InactiveSince(_) => ContentPurpose::Normal,
-                            },
+                            purpose: self.get_purpose(),
                         }
-                    };
-
-                    match (self.physical_keyboard, self.visibility_override) {
-                        (_, visibility::State::ForcedHidden) => animation::Outcome::Hidden,
-                        (_, visibility::State::ForcedVisible) => visible,
-                        (Presence::Present, visibility::State::NotForced) => animation::Outcome::Hidden,
-                        (Presence::Missing, visibility::State::NotForced) => match self.im {
-                            InputMethod::Active(_) => visible,
-                            InputMethod::InactiveSince(since) => {
-                                if now < since + animation::HIDING_TIMEOUT { visible }
-                                else { animation::Outcome::Hidden }
-                            },
-                        },
                     }
+                } else {
+                    animation::Outcome::Hidden
                 }
-            },
+            }
+        };
+        let allocated_height = match (&panel, &self.panel_allocation) {
+            (animation::Outcome::Visible { output, .. }, Some(allocation))
+                if allocation.output == *output
+            => allocation.height,
+            _ => 0,
+        };
+        Outcome {
+            panel,
+            allocated_height,
             im: self.im.clone(),
+            save_scale: self.scale_to_save,
+            one_handed: self.one_handed,
+            smart_punctuation: self.smart_punctuation,
         }
     }
 
     /// Returns the next time to update the outcome.
     fn get_next_wake(&self, now: Instant) -> Option<Instant> {
-        match self {
+        let hiding = match self {
             Self {
                 visibility_override: visibility::State::NotForced,
                 im: InputMethod::InactiveSince(since),
@@ -534,6 +1002,20 @@ impl ActorState for Application {
                 else { None }
             }
             _ => None,
+        };
+        let slide = if self.reduce_motion {
+            None
+        } else {
+            let end = self.get_slide(now).get_end();
+            if now < end {
+                Some(cmp::min(now + animation::FRAME_DURATION, end))
+            } else {
+                None
+            }
+        };
+        match (hiding, slide) {
+            (Some(hiding), Some(slide)) => Some(cmp::min(hiding, slide)),
+            (hiding, slide) => hiding.or(slide),
         }
     }
 }
@@ -543,8 +1025,12 @@ impl ActorState for Application {
 pub mod test {
     use super::*;
     use crate::outputs::c::WlOutput;
+    use gio::Settings;
     use std::time::Duration;
 
+    // Traits
+    use gdk::prelude::SettingsExt;
+
     fn imdetails_new() -> InputMethodDetails {
         InputMethodDetails {
             purpose: ContentPurpose::Normal,
@@ -567,6 +1053,7 @@ pub mod test {
                 current_mode: None,
                 geometry: None,
                 scale: 1,
+                name: None,
             },
         );
         Application {
@@ -576,6 +1063,20 @@ pub mod test {
         }
     }
 
+    /// A shown panel on the Librem 5, at its default size
+    fn application_on_librem5(start: Instant) -> Application {
+        let id = fake_output_id(1);
+        let mut outputs = HashMap::new();
+        outputs.insert(id, make_output(720, 1440, 65, 130, 2));
+        Application {
+            im: InputMethod::Active(imdetails_new()),
+            preferred_output: Some(id),
+            outputs,
+            panel_scale: Some(1.0),
+            ..Application::new(start)
+        }
+    }
+
     /// Test the original delay scenario: no flicker on quick switches.
     #[test]
     fn avoid_hide() {
@@ -751,6 +1252,558 @@ pub mod test {
 
     }
 
+    /// The panel shows for text input, despite the physical keyboard.
+    #[test]
+    fn policy_always_when_text_input() {
+        let start = Instant::now();
+        let mut now = start;
+        let state = Application {
+            im: InputMethod::InactiveSince(now),
+            physical_keyboard: Presence::Present,
+            visibility_policy: visibility::Policy::AlwaysWhenTextInput,
+            ..application_with_fake_output(start)
+        };
+        now += Duration::from_secs(1);
+
+        let state = state.apply_event(Event::InputMethod(InputMethod::Active(imdetails_new())), now);
+        assert_matches!(
+            state.get_outcome(now).panel,
+            animation::Outcome::Visible{..},
+            "Failed to show with a physical keyboard",
+        );
+
+        now += Duration::from_secs(1);
+        let state = state.apply_event(Event::InputMethod(InputMethod::InactiveSince(now)), now);
+        now += Duration::from_secs(1);
+        assert_eq!(state.get_outcome(now).panel, animation::Outcome::Hidden);
+    }
+
+    /// The physical keyboard takes precedence over the user's request.
+    #[test]
+    fn policy_never_with_physical_keyboard() {
+        let start = Instant::now();
+        let mut now = start;
+        let state = Application {
+            im: InputMethod::Active(imdetails_new()),
+            physical_keyboard: Presence::Missing,
+            visibility_policy: visibility::Policy::NeverWithPhysicalKeyboard,
+            ..application_with_fake_output(start)
+        };
+        assert_matches!(state.get_outcome(now).panel, animation::Outcome::Visible{..});
+
+        now += Duration::from_secs(1);
+        let state = state.apply_event(Event::PhysicalKeyboard(Presence::Present), now);
+        let state = state.apply_event(Event::Visibility(visibility::Event::ForceVisible), now);
+        assert_eq!(
+            state.get_outcome(now).panel,
+            animation::Outcome::Hidden,
+            "Forced visible despite the physical keyboard",
+        );
+    }
+
+    /// Text input alone doesn't show the panel.
+    #[test]
+    fn policy_manual_only() {
+        let start = Instant::now();
+        let mut now = start;
+        let state = Application {
+            im: InputMethod::InactiveSince(now),
+            physical_keyboard: Presence::Missing,
+            visibility_policy: visibility::Policy::ManualOnly,
+            ..application_with_fake_output(start)
+        };
+        now += Duration::from_secs(1);
+
+        let state = state.apply_event(Event::InputMethod(InputMethod::Active(imdetails_new())), now);
+        assert_eq!(
+            state.get_outcome(now).panel,
+            animation::Outcome::Hidden,
+            "Appeared without being asked",
+        );
+
+        let state = state.apply_event(Event::Visibility(visibility::Event::ForceVisible), now);
+        assert_matches!(
+            state.get_outcome(now).panel,
+            animation::Outcome::Visible{..},
+            "Failed to show on request",
+        );
+    }
+
+    /// Changing the policy applies to the current state.
+    #[test]
+    fn policy_changed() {
+        let start = Instant::now();
+        let state = Application {
+            im: InputMethod::Active(imdetails_new()),
+            physical_keyboard: Presence::Present,
+            ..application_with_fake_output(start)
+        };
+        assert_eq!(state.get_outcome(start).panel, animation::Outcome::Hidden);
+
+        let state = state.apply_event(
+            Event::VisibilityPolicy(visibility::Policy::AlwaysWhenTextInput),
+            start,
+        );
+        assert_matches!(state.get_outcome(start).panel, animation::Outcome::Visible{..});
+    }
+
+    /// The placement chosen by the user reaches the panel.
+    #[test]
+    fn placement() {
+        let start = Instant::now();
+        let state = Application {
+            im: InputMethod::Active(imdetails_new()),
+            ..application_with_fake_output(start)
+        };
+        let top = panel::Placement { anchor: panel::Anchor::Top, exclusive: false };
+        let state = state.apply_event(Event::PanelPlacement(top), start);
+        assert_matches!(
+            state.get_outcome(start).panel,
+            animation::Outcome::Visible{ placement, .. } if placement == top
+        );
+    }
+
+    fn get_shown_height(state: &Application, now: Instant) -> Option<u32> {
+        match state.get_outcome(now).panel {
+            animation::Outcome::Visible { height, .. } => Some(height.pixels),
+            animation::Outcome::Hidden => None,
+        }
+    }
+
+    fn application_with_animation(start: Instant) -> Application {
+        Application {
+            reduce_motion: false,
+            ..application_on_librem5(start)
+        }
+    }
+
+    /// The panel grows step by step, until it reaches the full height.
+    #[test]
+    fn slide_in() {
+        let start = Instant::now();
+        let state = Application {
+            im: InputMethod::InactiveSince(start - Duration::from_secs(1)),
+            ..application_with_animation(start)
+        };
+        assert_eq!(get_shown_height(&state, start), None);
+
+        let state = state.apply_event(Event::InputMethod(InputMethod::Active(imdetails_new())), start);
+        assert_eq!(get_shown_height(&state, start), None);
+        assert_eq!(
+            state.get_next_wake(start),
+            Some(start + animation::FRAME_DURATION),
+        );
+
+        let midway = start + animation::SLIDE_DURATION / 2;
+        assert_matches!(get_shown_height(&state, midway), Some(h) if h > 0 && h < 420);
+
+        let end = start + animation::SLIDE_DURATION;
+        assert_eq!(get_shown_height(&state, end), Some(420));
+        assert_eq!(state.get_next_wake(end), None);
+    }
+
+    /// After the hiding timeout, the panel shrinks without further events.
+    #[test]
+    fn slide_out() {
+        let start = Instant::now();
+        let state = Application {
+            im: InputMethod::Active(imdetails_new()),
+            slide: animation::Slide { shown: true, start, from: 1.0 },
+            ..application_with_animation(start)
+        };
+        let now = start + Duration::from_secs(1);
+        let state = state.apply_event(Event::InputMethod(InputMethod::InactiveSince(now)), now);
+
+        let hiding = now + animation::HIDING_TIMEOUT;
+        assert_eq!(get_shown_height(&state, hiding), Some(420));
+        assert_eq!(state.get_next_wake(now), Some(hiding));
+
+        let midway = hiding + animation::SLIDE_DURATION / 2;
+        assert_matches!(get_shown_height(&state, midway), Some(h) if h > 0 && h < 420);
+        assert_eq!(
+            state.get_next_wake(midway),
+            Some(midway + animation::FRAME_DURATION),
+        );
+
+        let end = hiding + animation::SLIDE_DURATION;
+        assert_eq!(get_shown_height(&state, end), None);
+        assert_eq!(state.get_next_wake(end), None);
+    }
+
+    /// Hiding midway through showing returns from the part already shown.
+    #[test]
+    fn slide_reversed() {
+        let start = Instant::now();
+        let state = Application {
+            im: InputMethod::InactiveSince(start - Duration::from_secs(1)),
+            ..application_with_animation(start)
+        };
+        let state = state.apply_event(Event::InputMethod(InputMethod::Active(imdetails_new())), start);
+
+        let midway = start + animation::SLIDE_DURATION / 2;
+        let state = state.apply_event(Event::Visibility(visibility::Event::ForceHidden), midway);
+        let shown = get_shown_height(&state, midway).unwrap();
+
+        let later = midway + animation::SLIDE_DURATION / 4;
+        assert_matches!(get_shown_height(&state, later), Some(h) if h > 0 && h < shown);
+        assert_eq!(get_shown_height(&state, midway + animation::SLIDE_DURATION / 2), None);
+    }
+
+    /// Without animations, the panel appears and disappears at once.
+    #[test]
+    fn reduce_motion() {
+        let start = Instant::now();
+        let state = Application {
+            im: InputMethod::InactiveSince(start - Duration::from_secs(1)),
+            ..application_with_animation(start)
+        };
+        let state = state.apply_event(Event::ReduceMotion(true), start);
+        let state = state.apply_event(Event::InputMethod(InputMethod::Active(imdetails_new())), start);
+        assert_eq!(get_shown_height(&state, start), Some(420));
+        assert_eq!(state.get_next_wake(start), None);
+    }
+
+    fn make_output(pixel_width: i32, pixel_height: i32, physical_width: i32, physical_height: i32, scale: i32) -> OutputState {
+        use crate::outputs::{Mode, Geometry, c, Size};
+        OutputState {
+            current_mode: Some(Mode {
+                width: pixel_width,
+                height: pixel_height,
+            }),
+            geometry: Some(Geometry {
+                transform: c::Transform::Normal,
+                phys_size: Size {
+                    width: Some(Millimeter(physical_width)),
+                    height: Some(Millimeter(physical_height)),
+                },
+            }),
+            scale,
+            name: None,
+        }
+    }
+
+    /// The panel moves to the output chosen by the user, and back.
+    #[test]
+    fn pinned_output() {
+        let start = Instant::now();
+        let phone = fake_output_id(1);
+        let monitor = fake_output_id(2);
+        let state = Application {
+            im: InputMethod::Active(imdetails_new()),
+            ..Application::new(start)
+        };
+        let add_output = |state: Application, id, output| state.apply_event(
+            Event::Output(outputs::Event {
+                output: id,
+                change: outputs::ChangeType::Altered(output),
+            }),
+            start,
+        );
+        let state = add_output(state, phone, OutputState {
+            name: Some("DSI-1".into()),
+            ..make_output(720, 1440, 65, 130, 2)
+        });
+        let state = add_output(state, monitor, OutputState {
+            name: Some("HDMI-A-1".into()),
+            ..make_output(1920, 1080, 520, 290, 1)
+        });
+        assert_eq!(state.preferred_output, Some(phone));
+
+        let state = state.apply_event(
+            Event::PinnedOutput(Some("HDMI-A-1".into())),
+            start,
+        );
+        assert_eq!(state.preferred_output, Some(monitor));
+
+        let state = state.apply_event(Event::PinnedOutput(None), start);
+        assert_eq!(state.preferred_output, Some(phone));
+    }
+
+    /// The layout gets moved only when the docked side changes.
+    #[test]
+    fn one_handed() {
+        use crate::event_loop::Outcome;
+
+        let start = Instant::now();
+        let state = application_with_fake_output(start);
+        let undocked = state.get_outcome(start);
+
+        let state = state.apply_event(Event::OneHanded(Some(layout::Side::Left)), start);
+        let docked = state.get_outcome(start);
+        assert_eq!(
+            undocked.get_commands_to_reach(&docked).one_handed,
+            Some(Some(layout::Side::Left)),
+        );
+        assert_eq!(docked.get_commands_to_reach(&docked).one_handed, None);
+
+        let state = state.apply_event(Event::OneHanded(None), start);
+        assert_eq!(
+            docked.get_commands_to_reach(&state.get_outcome(start)).one_handed,
+            Some(None),
+        );
+    }
+
+    /// Dragging the panel edge changes the height within the limits.
+    #[test]
+    fn resize() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = Application {
+            // The unscaled height is 420 pixels, at a scale factor of 2.
+            panel_allocation: Some(panel::Allocation {
+                output: id,
+                wanted_height: 210,
+                width: 360,
+                height: 210,
+            }),
+            ..application_on_librem5(start)
+        };
+
+        let get_height = |state: &Application| match state.get_outcome(start).panel {
+            animation::Outcome::Visible { height, .. } => height.pixels,
+            animation::Outcome::Hidden => panic!("Panel hidden"),
+        };
+
+        let resize = |state: Application, offset| state.apply_event(
+            Event::PanelResize(resize::Event::Drag { offset }),
+            start,
+        );
+
+        // Anchored at the bottom, so dragging up makes the panel taller.
+        let state = resize(state, -105);
+        assert_eq!(state.panel_scale, Some(1.5));
+        assert_eq!(get_height(&state), 630);
+
+        let state = resize(state, 160);
+        assert_eq!(state.panel_scale, Some(MIN_SCALE));
+        assert_eq!(get_height(&state), 210);
+
+        // Limited by the scale first, and not by 2/3 of the screen.
+        let state = resize(state, -490);
+        assert_eq!(state.panel_scale, Some(MAX_SCALE));
+        assert_eq!(get_height(&state), 840);
+    }
+
+    /// When anchored at the top, the panel doesn't move while resizing,
+    /// so the height follows the offset from the start.
+    #[test]
+    fn resize_from_top() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let allocation = |height| panel::Allocation {
+            output: id,
+            wanted_height: height,
+            width: 360,
+            height,
+        };
+        let state = Application {
+            panel_allocation: Some(allocation(210)),
+            panel_placement: panel::Placement {
+                anchor: panel::Anchor::Top,
+                exclusive: true,
+            },
+            ..application_on_librem5(start)
+        };
+
+        let resize = |state: Application, offset| state.apply_event(
+            Event::PanelResize(resize::Event::Drag { offset }),
+            start,
+        );
+
+        let state = resize(state, 105);
+        assert_eq!(state.panel_scale, Some(1.5));
+
+        let state = state.apply_event(Event::PanelConfigured(allocation(315)), start);
+        let state = resize(state, 105);
+        assert_eq!(state.panel_scale, Some(1.5));
+
+        let state = state.apply_event(Event::PanelResize(resize::Event::Done), start);
+        assert_eq!(state.resizing_from, None);
+    }
+
+    /// The chosen scale replaces the one for the orientation, and gets saved once.
+    #[test]
+    fn resize_done() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = Application {
+            panel_allocation: Some(panel::Allocation {
+                output: id,
+                wanted_height: 210,
+                width: 360,
+                height: 210,
+            }),
+            ..application_on_librem5(start)
+        };
+
+        let state = state.apply_event(
+            Event::PanelResize(resize::Event::Drag { offset: -105 }),
+            start,
+        );
+        assert_eq!(state.get_outcome(start).save_scale, None);
+
+        let state = state.apply_event(Event::PanelResize(resize::Event::Done), start);
+        assert_eq!(state.panel_scale, None);
+        assert_eq!(state.panel_scales.vertical, 1.5);
+        assert_eq!(
+            state.get_outcome(start).save_scale,
+            Some((panel::Orientation::Vertical, 1.5)),
+        );
+
+        let state = state.apply_event(Event::TimeoutReached(start), start);
+        assert_eq!(state.get_outcome(start).save_scale, None);
+        assert_eq!(state.panel_scales.vertical, 1.5);
+    }
+
+    /// A tight allocation switches to the wide layout, without asking for another size.
+    #[test]
+    fn squashed() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = application_on_librem5(start);
+
+        let get_panel = |state: &Application| match state.get_outcome(start).panel {
+            animation::Outcome::Visible { height, contents, .. }
+                => (height.as_scaled_ceiling(), contents.kind),
+            animation::Outcome::Hidden => panic!("Panel hidden"),
+        };
+
+        assert_eq!(get_panel(&state), (210, ArrangementKind::Base));
+
+        let configure = |state: Application, height| state.apply_event(
+            Event::PanelConfigured(panel::Allocation {
+                output: id,
+                wanted_height: 210,
+                width: 360,
+                height,
+            }),
+            start,
+        );
+
+        let state = configure(state, 200);
+        assert_eq!(get_panel(&state), (210, ArrangementKind::Base));
+
+        let state = configure(state, 120);
+        assert_eq!(get_panel(&state), (210, ArrangementKind::Wide));
+    }
+
+    /// The allocated height is reported only while the panel is shown.
+    #[test]
+    fn allocated_height() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = application_on_librem5(start);
+        assert_eq!(state.get_outcome(start).allocated_height, 0);
+
+        let state = state.apply_event(
+            Event::PanelConfigured(panel::Allocation {
+                output: id,
+                wanted_height: 210,
+                width: 360,
+                height: 200,
+            }),
+            start,
+        );
+        assert_eq!(state.get_outcome(start).allocated_height, 200);
+
+        let state = state.apply_event(
+            Event::Visibility(visibility::Event::ForceHidden),
+            start,
+        );
+        assert_eq!(state.get_outcome(start).allocated_height, 0);
+    }
+
+    /// The fractional scale from the compositor replaces the integer one of the output.
+    #[test]
+    fn fractional_scale() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = application_on_librem5(start);
+
+        let get_height = |state: &Application| match state.get_outcome(start).panel {
+            animation::Outcome::Visible { height, .. }
+                => (height.pixels, height.as_scaled_ceiling()),
+            animation::Outcome::Hidden => panic!("Panel hidden"),
+        };
+
+        assert_eq!(get_height(&state), (420, 210));
+
+        let state = state.apply_event(
+            Event::PanelScale { output: id, scale: panel::Scale(180) },
+            start,
+        );
+        assert_eq!(get_height(&state), (420, 280));
+
+        // Dragging works in logical pixels of the fractional scale.
+        let state = state.apply_event(
+            Event::PanelConfigured(panel::Allocation {
+                output: id,
+                wanted_height: 280,
+                width: 400,
+                height: 280,
+            }),
+            start,
+        );
+        let state = state.apply_event(
+            Event::PanelResize(resize::Event::Drag { offset: 0 }),
+            start,
+        );
+        assert_eq!(state.panel_scale, Some(1.0));
+    }
+
+    /// The panel fits the layout once it's loaded, and only that layout.
+    #[test]
+    fn layout_loaded() {
+        let start = Instant::now();
+        let state = application_on_librem5(start);
+        let get_panel = |state: &Application| match state.get_outcome(start).panel {
+            animation::Outcome::Visible { height, contents, .. } => (height.pixels, contents),
+            animation::Outcome::Hidden => panic!("Panel hidden"),
+        };
+        let (height, contents) = get_panel(&state);
+        assert_eq!(height, 420);
+
+        let square = Proportions {
+            rows: 4,
+            aspect_ratio: Rational { numerator: 1200, denominator: 1200 },
+        };
+        let other = animation::Contents {
+            name: "de".into(),
+            ..contents.clone()
+        };
+        let state = state.apply_event(
+            Event::LayoutLoaded { contents: other, proportions: square },
+            start,
+        );
+        assert_eq!(get_panel(&state).0, 420);
+
+        let state = state.apply_event(
+            Event::LayoutLoaded { contents, proportions: square },
+            start,
+        );
+        assert_eq!(get_panel(&state).0, 720);
+    }
+
+    /// Layouts with fewer rows need less space, if the screen isn't too short.
+    #[test]
+    fn size_from_rows() {
+        // Librem 5, horizontal
+        let output = make_output(1440, 720, 130, 65, 1);
+        let height = |rows| Application::get_preferred_height_and_arrangement(
+            &output,
+            panel::Scale::from_integer(1),
+            1.0,
+            |_kind| Proportions {
+                rows,
+                aspect_ratio: Rational { numerator: 500, denominator: 1600 },
+            },
+        ).map(|(size, _arrangement)| size.pixels);
+        assert_eq!(height(3), Some(316));
+        // Limited to half of the screen
+        assert_eq!(height(4), Some(360));
+        assert_eq!(height(6), Some(360));
+    }
+
 // scaling-tests
     // TODO: Combine `scaling_test_base` and `scaling_test_wide` into a single function.
     fn scaling_test_base(pixel_width: i32, pixel_height: i32, physical_width: i32, physical_height: i32, scale: i32, expected_pixel_height: u32) {
@@ -770,24 +1823,36 @@ pub mod test {
                  (" Current scaling-multiplier in horizontal orientation: ", scale_setting_horizontal.to_string())}
          else {("", "".to_string())};
 
-        assert_eq!(
-            Application::get_preferred_height_and_arrangement(&OutputState {
-                current_mode: Some(Mode {
-                    width: pixel_width,
-                    height: pixel_height,
-                }),
-                geometry: Some(Geometry{
-                    transform: c::Transform::Normal,
-                    phys_size: Size {
-                        width: Some(Millimeter(physical_width)),
-                        height: Some(Millimeter(physical_height)),
-                    },
-                }),
-                scale,
+        let output = OutputState {
+            current_mode: Some(Mode {
+                width: pixel_width,
+                height: pixel_height,
+            }),
+            geometry: Some(Geometry{
+                transform: c::Transform::Normal,
+                phys_size: Size {
+                    width: Some(Millimeter(physical_width)),
+                    height: Some(Millimeter(physical_height)),
+                },
             }),
+            scale,
+            name: None,
+        };
+        let scale_setting = panel::Scales {
+            vertical: scale_setting_vertical,
+            horizontal: scale_setting_horizontal,
+        }.get(Application::get_orientation(&output));
+
+        assert_eq!(
+            Application::get_preferred_height_and_arrangement(
+                &output,
+                panel::Scale::from_integer(scale as u32),
+                scale_setting,
+                Application::get_default_proportions,
+            ),
             Some((
                 PixelSize {
-                    scale_factor: scale as u32,
+                    scale_factor: panel::Scale::from_integer(scale as u32),
                     pixels: if pixel_width < pixel_height {
                              cmp::min((expected_pixel_height as f64 * scale_setting_vertical) as u32,
                                                (pixel_height as f64 * (2.0 / 3.0)) as u32)
@@ -803,6 +1868,14 @@ pub mod test {
     }
 
     fn scaling_test_wide(pixel_width: i32, pixel_height: i32, physical_width: i32, physical_height: i32, scale: i32, expected_pixel_height: u32) {
+        scaling_test_arrangement(pixel_width, pixel_height, physical_width, physical_height, scale, expected_pixel_height, ArrangementKind::Wide)
+    }
+
+    fn scaling_test_split(pixel_width: i32, pixel_height: i32, physical_width: i32, physical_height: i32, scale: i32, expected_pixel_height: u32) {
+        scaling_test_arrangement(pixel_width, pixel_height, physical_width, physical_height, scale, expected_pixel_height, ArrangementKind::Split)
+    }
+
+    fn scaling_test_arrangement(pixel_width: i32, pixel_height: i32, physical_width: i32, physical_height: i32, scale: i32, expected_pixel_height: u32, arrangement: ArrangementKind) {
         use crate::outputs::{Mode, Geometry, c, Size};
 
         // TODO: Test with different settings for the scaling; at least the default (1.0), and another value.
@@ -819,24 +1892,36 @@ pub mod test {
                  (" Current scaling-multiplier in horizontal orientation: ", scale_setting_horizontal.to_string())}
          else {("", "".to_string())};
 
-        assert_eq!(
-            Application::get_preferred_height_and_arrangement(&OutputState {
-                current_mode: Some(Mode {
-                    width: pixel_width,
-                    height: pixel_height,
-                }),
-                geometry: Some(Geometry{
-                    transform: c::Transform::Normal,
-                    phys_size: Size {
-                        width: Some(Millimeter(physical_width)),
-                        height: Some(Millimeter(physical_height)),
-                    },
-                }),
-                scale,
+        let output = OutputState {
+            current_mode: Some(Mode {
+                width: pixel_width,
+                height: pixel_height,
+            }),
+            geometry: Some(Geometry{
+                transform: c::Transform::Normal,
+                phys_size: Size {
+                    width: Some(Millimeter(physical_width)),
+                    height: Some(Millimeter(physical_height)),
+                },
             }),
+            scale,
+            name: None,
+        };
+        let scale_setting = panel::Scales {
+            vertical: scale_setting_vertical,
+            horizontal: scale_setting_horizontal,
+        }.get(Application::get_orientation(&output));
+
+        assert_eq!(
+            Application::get_preferred_height_and_arrangement(
+                &output,
+                panel::Scale::from_integer(scale as u32),
+                scale_setting,
+                Application::get_default_proportions,
+            ),
             Some((
                 PixelSize {
-                    scale_factor: scale as u32,
+                    scale_factor: panel::Scale::from_integer(scale as u32),
                     pixels: if pixel_width < pixel_height {
                              cmp::min((expected_pixel_height as f64 * scale_setting_vertical) as u32,
                                                (pixel_height as f64 * (2.0 / 3.0)) as u32)
@@ -846,7 +1931,7 @@ pub mod test {
                                                (pixel_height as f64 * (2.0 / 3.0)) as u32)
                                                },
                 },
-                ArrangementKind::Wide,
+                arrangement,
             )), "Height of the panel is different.{}{}", log_message_about_scaling, value_of_scaling_setting_for_log
         );
     }
@@ -1012,12 +2097,12 @@ pub mod test {
     #[test]
     fn size_galaxy_tab_a_8_0() {scaling_test_wide(768, 1024, 122, 163, 1, 240)}
     #[test]
-    fn size_galaxy_tab_a_8_0_horizontal() {scaling_test_wide(1024, 768, 163, 122, 1, 256)}
+    fn size_galaxy_tab_a_8_0_horizontal() {scaling_test_split(1024, 768, 163, 122, 1, 256)}
 
     #[test]
     fn size_galaxy_tab_s2_9_7() {scaling_test_wide(1536, 2048, 148, 197, 2, 480)}
     #[test]
-    fn size_galaxy_tab_s2_9_7_horizontal() {scaling_test_wide(2048, 1536, 197, 148, 2, 512)}
+    fn size_galaxy_tab_s2_9_7_horizontal() {scaling_test_split(2048, 1536, 197, 148, 2, 512)}
 
   // 16:10
     #[test]
@@ -1028,12 +2113,12 @@ pub mod test {
     #[test]
     fn size_pinetab2() {scaling_test_wide(800, 1280, 136, 218, 1, 250)}
     #[test]
-    fn size_pinetab2_horizontal() {scaling_test_wide(1280, 800, 218, 136, 1, 266)}
+    fn size_pinetab2_horizontal() {scaling_test_split(1280, 800, 218, 136, 1, 266)}
 
     #[test]
     fn size_librem_11() {scaling_test_wide(1600, 2560, 155, 248, 1, 500)}
     #[test]
-    fn size_librem_11_horizontal() {scaling_test_wide(2560, 1600, 248, 155, 1, 533)}
+    fn size_librem_11_horizontal() {scaling_test_split(2560, 1600, 248, 155, 1, 533)}
 
   // 1.71:1
     #[test]
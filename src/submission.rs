@@ -20,18 +20,20 @@
 use std::collections::HashSet;
 use std::ffi::CString;
 
-use crate::vkeyboard::c::ZwpVirtualKeyboardV1;
 use crate::action::Modifier;
+use crate::composition::Composer;
 use crate::imservice;
-use crate::imservice::IMService;
+use crate::keyboard;
 use crate::keyboard::{ KeyCode, KeyStateId, Modifiers, PressType };
 use crate::layout;
+use crate::logging;
+use crate::punctuation;
 use crate::util::vec_remove;
 use crate::vkeyboard;
-use crate::vkeyboard::VirtualKeyboard;
 
 // traits
 use std::iter::FromIterator;
+use crate::logging::Warn;
 
 /// Gathers stuff defined in C or called by C
 pub mod c {
@@ -77,12 +79,16 @@ enum SubmittedAction {
 }
 
 pub struct Submission {
-    imservice: Option<Box<IMService>>,
-    virtual_keyboard: VirtualKeyboard,
+    imservice: Option<Box<dyn imservice::Sink>>,
+    virtual_keyboard: Box<dyn vkeyboard::Sink>,
     modifiers_active: Vec<(KeyStateId, Modifier)>,
     pressed: Vec<(KeyStateId, SubmittedAction)>,
-    keymap_fds: Vec<vkeyboard::c::KeyMap>,
+    keymaps: Vec<vkeyboard::KeyMap>,
     keymap_idx: Option<usize>,
+    /// Present if the layout composes text.
+    composer: Option<Composer>,
+    /// Follows the smart-punctuation setting.
+    smart_punctuation: bool,
 }
 
 pub enum SubmitData<'a> {
@@ -92,17 +98,26 @@ pub enum SubmitData<'a> {
 }
 
 impl Submission {
-    pub fn new(vk: ZwpVirtualKeyboardV1, imservice: Option<Box<IMService>>) -> Self {
+    pub fn new(
+        virtual_keyboard: Box<dyn vkeyboard::Sink>,
+        imservice: Option<Box<dyn imservice::Sink>>,
+    ) -> Self {
         Submission {
             imservice,
             modifiers_active: Vec::new(),
-            virtual_keyboard: VirtualKeyboard(vk),
+            virtual_keyboard,
             pressed: Vec::new(),
-            keymap_fds: Vec::new(),
+            keymaps: Vec::new(),
             keymap_idx: None,
+            composer: None,
+            smart_punctuation: false,
         }
     }
 
+    pub fn set_smart_punctuation(&mut self, enabled: bool) {
+        self.smart_punctuation = enabled;
+    }
+
     /// Sends a submit text event if possible;
     /// otherwise sends key press and makes a note of it
     pub fn handle_press(
@@ -112,6 +127,21 @@ impl Submission {
         keycodes: &Vec<KeyCode>,
         time: Timestamp,
     ) {
+        // Committing the preedit makes the surrounding text outdated.
+        let had_preedit = self.imservice.as_ref()
+            .map(|imservice| !imservice.get_preedit_string().is_empty())
+            .unwrap_or(false);
+
+        if self.compose(&data) {
+            self.pressed.push((key_id, SubmittedAction::IMService));
+            return;
+        }
+
+        if !had_preedit && self.punctuate(&data) {
+            self.pressed.push((key_id, SubmittedAction::IMService));
+            return;
+        }
+
         let mods_are_on = !self.modifiers_active.is_empty();
 
         let was_committed_as_text = match (&mut self.imservice, mods_are_on) {
@@ -217,6 +247,170 @@ impl Submission {
         };
     }
     
+    /// Passes the press through the composer, if the layout has one.
+    /// Returns true when nothing else needs to be submitted.
+    fn compose(&mut self, data: &SubmitData) -> bool {
+        let mods_are_on = !self.modifiers_active.is_empty();
+        let (composer, imservice) = match (&mut self.composer, &mut self.imservice) {
+            (Some(composer), Some(imservice)) => (composer, imservice),
+            _ => return false,
+        };
+
+        if !imservice.is_active() {
+            // The preedit is gone together with the text field.
+            composer.flush();
+            return false;
+        }
+
+        // The preedit got committed elsewhere, e.g. on focus change.
+        if imservice.get_preedit_string() != composer.get_preedit() {
+            composer.flush();
+        }
+
+        let composed = match (data, mods_are_on) {
+            (SubmitData::Text(text), false) => {
+                composer.push(&text.to_string_lossy()).ok()
+            },
+            // Decompose before erasing anything already committed.
+            (SubmitData::Erase, false) => match composer.pop() {
+                true => Some(String::new()),
+                false => None,
+            },
+            _ => None,
+        };
+
+        // Anything else ends composition.
+        let (done, preedit) = match &composed {
+            Some(done) => (done.clone(), composer.get_preedit()),
+            None => (composer.flush(), String::new()),
+        };
+
+        if !done.is_empty() || composed.is_some() {
+            send_composed(imservice, done, preedit);
+        }
+
+        composed.is_some()
+    }
+
+    /// Adjusts spaces around punctuation, if enabled.
+    /// Returns true if the text got submitted.
+    fn punctuate(&mut self, data: &SubmitData) -> bool {
+        let text = match data {
+            SubmitData::Text(text) => text.to_string_lossy(),
+            _ => return false,
+        };
+        if !self.smart_punctuation || !self.modifiers_active.is_empty() {
+            return false;
+        }
+        let imservice = match &mut self.imservice {
+            Some(imservice) if imservice.is_active() => imservice,
+            _ => return false,
+        };
+        if !punctuation::is_allowed(imservice.get_content_purpose()) {
+            return false;
+        }
+        let edit = match imservice.get_text_before_cursor()
+            .and_then(|before| punctuation::get_edit(before, &text))
+        {
+            Some(edit) => edit,
+            None => return false,
+        };
+        if edit.is_empty() {
+            return true;
+        }
+        let text = CString::new(edit.text)
+            .expect("Punctuation contains NUL");
+        imservice.delete_surrounding_text(edit.delete_before as u32, 0)
+            .and_then(|()| imservice.commit_string(&text))
+            .and_then(|()| imservice.commit())
+            .is_ok()
+    }
+
+    /// Returns the composition candidate to show in the slot.
+    pub fn get_candidate(&self, slot: usize) -> Option<String> {
+        self.composer.as_ref()
+            .and_then(|composer| composer.get_candidate(slot))
+    }
+
+    pub fn select_candidate(&mut self, slot: usize) {
+        if let (Some(composer), Some(imservice)) = (&mut self.composer, &mut self.imservice) {
+            if let Some(done) = composer.select_candidate(slot) {
+                send_composed(imservice, done, composer.get_preedit());
+            }
+        }
+    }
+
+    pub fn turn_candidate_page(&mut self, forward: bool) {
+        if let Some(composer) = &mut self.composer {
+            composer.turn_candidate_page(forward);
+        }
+    }
+
+    /// Commits the text being composed as it is.
+    fn flush_composer(&mut self) {
+        if let (Some(composer), Some(imservice)) = (&mut self.composer, &mut self.imservice) {
+            let done = composer.flush();
+            if !done.is_empty() {
+                send_composed(imservice, done, String::new());
+            }
+        }
+    }
+
+    /// Submits text which doesn't come from any button.
+    ///
+    /// Uses the input method when possible.
+    /// Otherwise, types the text with a temporary keymap,
+    /// and restores the layout's keymap afterwards.
+    pub fn submit_text(&mut self, text: &str, time: Timestamp) {
+        self.flush_composer();
+        let mods_are_on = !self.modifiers_active.is_empty();
+        if let (Some(imservice), false) = (&mut self.imservice, mods_are_on) {
+            let ctext = CString::new(text)
+                .expect("Submitted text contains NUL");
+            let result = imservice.commit_string(&ctext)
+                .and_then(|()| imservice.commit());
+            if let Ok(()) = result {
+                return;
+            }
+        }
+
+        let names: Vec<String> = text.chars()
+            .map(|c| format!("U{:04X}", c as u32))
+            .collect();
+        let keycodes = keyboard::generate_keycodes(names.clone());
+        let keymaps = match keyboard::generate_keymaps(keycodes.clone()) {
+            Ok(keymaps) => keymaps,
+            Err(e) => {
+                log_print!(
+                    logging::Level::Bug,
+                    "Can't generate keymap for {}: {}", text, e,
+                );
+                return;
+            },
+        };
+        let keymaps: Vec<_> = keymaps.into_iter()
+            .map(|keymap| vkeyboard::KeyMap::new(
+                CString::new(keymap).expect("Keymap contains NUL")
+            ))
+            .collect();
+
+        let previous_keymap_idx = self.keymap_idx;
+        self.clear_all_modifiers();
+        self.release_all_virtual_keys(time);
+        for name in names {
+            let keycode = &keycodes[&name];
+            self.virtual_keyboard.update_keymap(&keymaps[keycode.keymap_idx]);
+            self.virtual_keyboard.switch(keycode.code, PressType::Pressed, time);
+            self.virtual_keyboard.switch(keycode.code, PressType::Released, time);
+        }
+
+        // Force the layout's keymap back, the same one as before.
+        self.keymap_idx = None;
+        if !self.keymaps.is_empty() {
+            self.select_keymap(previous_keymap_idx.unwrap_or(0), time);
+        }
+    }
+
     pub fn handle_add_modifier(
         &mut self,
         key_id: KeyStateId,
@@ -302,7 +496,7 @@ impl Submission {
             let modifiers = self.modifiers_active.clone();
             self.clear_all_modifiers();
             self.release_all_virtual_keys(time);
-            let keymap = &self.keymap_fds[idx];
+            let keymap = &self.keymaps[idx];
             self.virtual_keyboard.update_keymap(keymap);
             self.modifiers_active = modifiers;
             self.update_modifiers();
@@ -310,10 +504,14 @@ impl Submission {
     }
     
     pub fn use_layout(&mut self, layout: &layout::LayoutData, time: Timestamp) {
-        self.keymap_fds = layout.keymaps.iter()
-            .map(|keymap_str| vkeyboard::c::KeyMap::from_cstr(
-                keymap_str.as_c_str()
-            ))
+        // Leave the text as it appears when switching away.
+        self.flush_composer();
+        let page_size = layout.get_candidate_count();
+        self.composer = layout.composition.clone()
+            .map(|kind| Composer::new(kind, page_size));
+
+        self.keymaps = layout.keymaps.iter()
+            .map(|keymap_str| vkeyboard::KeyMap::new(keymap_str.clone()))
             .collect();
         self.keymap_idx = None;
 
@@ -327,3 +525,206 @@ impl Submission {
         self.clear_all_modifiers();
     }
 }
+
+/// Replaces the preedit, committing finished text.
+fn send_composed(imservice: &mut dyn imservice::Sink, done: String, preedit: String) {
+    let done = CString::new(done).expect("Composed text contains NUL");
+    let preedit = CString::new(preedit).expect("Preedit contains NUL");
+    imservice.commit_string(&done)
+        .and_then(|()| imservice.set_preedit_string(&preedit))
+        .and_then(|()| imservice.commit())
+        .or_print(logging::Problem::Warning, "Can't submit composed text");
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::imservice::{ ContentPurpose, SubmitError };
+
+    /// What reached the compositor or the application, in order.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Sent {
+        Key(u32, PressType),
+        Modifiers(Modifiers),
+        KeyMap(CString),
+        CommitString(String),
+        Preedit(String),
+        DeleteSurrounding(u32, u32),
+        Commit,
+    }
+
+    pub type Log = Rc<RefCell<Vec<Sent>>>;
+
+    pub struct FakeKeyboard(Log);
+
+    impl vkeyboard::Sink for FakeKeyboard {
+        fn switch(&self, keycode: u32, action: PressType, _time: Timestamp) {
+            self.0.borrow_mut().push(Sent::Key(keycode, action));
+        }
+
+        fn set_modifiers_state(&self, modifiers: Modifiers) {
+            self.0.borrow_mut().push(Sent::Modifiers(modifiers));
+        }
+
+        fn update_keymap(&self, keymap: &vkeyboard::KeyMap) {
+            self.0.borrow_mut().push(Sent::KeyMap(keymap.get_text().into()));
+        }
+    }
+
+    /// An input method with a text field which is always focused.
+    pub struct FakeIM {
+        log: Log,
+        preedit: String,
+        pending_preedit: String,
+        text_before_cursor: Option<String>,
+    }
+
+    impl imservice::Sink for FakeIM {
+        fn commit_string(&self, text: &CString) -> Result<(), SubmitError> {
+            let text = text.to_string_lossy().into_owned();
+            self.log.borrow_mut().push(Sent::CommitString(text));
+            Ok(())
+        }
+
+        fn set_preedit_string(&mut self, text: &CString) -> Result<(), SubmitError> {
+            self.pending_preedit = text.to_string_lossy().into_owned();
+            self.log.borrow_mut().push(Sent::Preedit(self.pending_preedit.clone()));
+            Ok(())
+        }
+
+        fn get_preedit_string(&self) -> &str {
+            &self.preedit
+        }
+
+        fn delete_surrounding_text(&self, before: u32, after: u32)
+            -> Result<(), SubmitError>
+        {
+            self.log.borrow_mut().push(Sent::DeleteSurrounding(before, after));
+            Ok(())
+        }
+
+        fn commit(&mut self) -> Result<(), SubmitError> {
+            self.preedit = std::mem::take(&mut self.pending_preedit);
+            self.log.borrow_mut().push(Sent::Commit);
+            Ok(())
+        }
+
+        fn is_active(&self) -> bool {
+            true
+        }
+
+        fn get_content_purpose(&self) -> ContentPurpose {
+            ContentPurpose::Normal
+        }
+
+        fn get_text_before_cursor(&self) -> Option<&str> {
+            self.text_before_cursor.as_deref()
+        }
+    }
+
+    /// Creates a submission which records everything it sends.
+    /// Without the input method, all text gets typed as keycodes.
+    /// The text before the cursor is unknown,
+    /// so that punctuation doesn't get adjusted.
+    pub fn make_submission(with_im: bool) -> (Submission, Log) {
+        make_submission_with_text(with_im, None)
+    }
+
+    fn make_submission_with_text(with_im: bool, text_before_cursor: Option<&str>)
+        -> (Submission, Log)
+    {
+        let log = Log::default();
+        let imservice: Option<Box<dyn imservice::Sink>> = match with_im {
+            true => Some(Box::new(FakeIM {
+                log: log.clone(),
+                preedit: String::new(),
+                pending_preedit: String::new(),
+                text_before_cursor: text_before_cursor.map(String::from),
+            })),
+            false => None,
+        };
+        let submission = Submission::new(
+            Box::new(FakeKeyboard(log.clone())),
+            imservice,
+        );
+        (submission, log)
+    }
+
+    /// Text outside of the keymap needs a keymap of its own,
+    /// and the layout's keymap comes back after it's typed.
+    #[test]
+    fn submit_text_switches_keymap() {
+        let (mut submission, log) = make_submission(false);
+        let layout = CString::new("layout keymap").unwrap();
+        submission.keymaps = vec![vkeyboard::KeyMap::new(layout.clone())];
+        submission.select_keymap(0, Timestamp(0));
+        log.borrow_mut().clear();
+
+        submission.submit_text("ü", Timestamp(1));
+
+        let keycodes = keyboard::generate_keycodes(vec!["U00FC".into()]);
+        let keymap = keyboard::generate_keymaps(keycodes.clone())
+            .unwrap()
+            .remove(0);
+        let code = keycodes["U00FC"].code;
+        assert_eq!(
+            log.borrow().clone(),
+            vec![
+                Sent::KeyMap(CString::new(keymap).unwrap()),
+                Sent::Key(code, PressType::Pressed),
+                Sent::Key(code, PressType::Released),
+                Sent::KeyMap(layout),
+                Sent::Modifiers(Modifiers::empty()),
+            ],
+        );
+    }
+
+    /// Layouts with many keysyms have many keymaps.
+    #[test]
+    fn submit_text_restores_keymap_index() {
+        let (mut submission, log) = make_submission(false);
+        let second = CString::new("second layout keymap").unwrap();
+        submission.keymaps = vec![
+            vkeyboard::KeyMap::new(CString::new("first layout keymap").unwrap()),
+            vkeyboard::KeyMap::new(second.clone()),
+        ];
+        submission.select_keymap(1, Timestamp(0));
+        log.borrow_mut().clear();
+
+        submission.submit_text("ü", Timestamp(1));
+
+        assert_eq!(submission.keymap_idx, Some(1));
+        assert!(log.borrow().contains(&Sent::KeyMap(second)));
+    }
+
+    #[test]
+    fn submit_text_through_im() {
+        let (mut submission, log) = make_submission(true);
+        submission.submit_text("ü", Timestamp(1));
+        assert_eq!(
+            log.borrow().clone(),
+            vec![Sent::CommitString("ü".into()), Sent::Commit],
+        );
+    }
+
+    /// A space after the automatic one is swallowed.
+    #[test]
+    fn punctuate_swallows_space() {
+        let (mut submission, log) = make_submission_with_text(true, Some("Hello. "));
+        submission.set_smart_punctuation(true);
+        let space = CString::new(" ").unwrap();
+        assert!(submission.punctuate(&SubmitData::Text(&space)));
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn punctuate_disabled() {
+        let (mut submission, log) = make_submission_with_text(true, Some("Hello. "));
+        let space = CString::new(" ").unwrap();
+        assert!(!submission.punctuate(&SubmitData::Text(&space)));
+        assert!(log.borrow().is_empty());
+    }
+}
@@ -1,6 +1,6 @@
 /*! Testing functionality */
 
-use crate::data::parsing::Layout;
+use crate::data::parsing::{ Layout, Parsed };
 use crate::logging;
 use xkbcommon::xkb;
 
@@ -70,7 +70,7 @@ fn check_sym_presence(
     }
 }
 
-fn check_layout(layout: Layout, allow_missing_return: bool) {
+fn check_layout(layout: Parsed, allow_missing_return: bool) {
     let handler = CountAndPrint::new();
     let (layout, mut handler) = layout.build(handler);
 
@@ -0,0 +1,99 @@
+/*! Entering arbitrary characters by their Unicode code point.
+ *
+ * While the mode is active, hex digit buttons accumulate a code point,
+ * and a commit button submits the resulting character.
+ */
+
+use std::ffi::CString;
+
+/// The longest code point, U+10FFFF, has 6 hex digits.
+const MAX_DIGITS: usize = 6;
+
+/// A code point in the middle of being entered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pending {
+    /// The view to go back to once the input is finished.
+    pub return_view: String,
+    /// Uppercase hex digits entered so far.
+    digits: String,
+}
+
+impl Pending {
+    pub fn new(return_view: String) -> Pending {
+        Pending {
+            return_view,
+            digits: String::new(),
+        }
+    }
+
+    /// Appends a digit. Digits beyond the longest code point are ignored.
+    pub fn push(&mut self, digit: u8) {
+        if self.digits.len() < MAX_DIGITS {
+            if let Some(c) = char::from_digit(digit as u32, 16) {
+                self.digits.push(c.to_ascii_uppercase());
+            }
+        }
+    }
+
+    pub fn pop(&mut self) {
+        self.digits.pop();
+    }
+
+    /// Returns the character, if the digits form a valid scalar value.
+    pub fn get_char(&self) -> Option<char> {
+        u32::from_str_radix(&self.digits, 16).ok()
+            .and_then(char::from_u32)
+    }
+
+    /// Text to show on the commit button:
+    /// the character itself when it's valid, the code otherwise.
+    pub fn get_label(&self) -> CString {
+        let label = match self.get_char() {
+            // Control characters would show up as garbage.
+            Some(c) if !c.is_control() => c.to_string(),
+            _ => format!("U+{}", self.digits),
+        };
+        CString::new(label)
+            // NUL is a control character, so it's always formatted as code.
+            .expect("Label contains NUL")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compose() {
+        let mut p = Pending::new("base".into());
+        assert_eq!(p.get_char(), None);
+        assert_eq!(p.get_label(), CString::new("U+").unwrap());
+        for d in &[0xe, 0x9] {
+            p.push(*d);
+        }
+        assert_eq!(p.get_char(), Some('\u{e9}'));
+        assert_eq!(p.get_label(), CString::new("é").unwrap());
+        p.pop();
+        assert_eq!(p.get_char(), Some('\u{e}'));
+        assert_eq!(p.get_label(), CString::new("U+E").unwrap());
+    }
+
+    #[test]
+    fn too_long() {
+        let mut p = Pending::new("base".into());
+        for d in &[1, 0, 0xf, 0xf, 0xf, 0xf, 0xf] {
+            p.push(*d);
+        }
+        assert_eq!(p.get_char(), Some('\u{10ffff}'));
+    }
+
+    #[test]
+    fn surrogate_invalid() {
+        let mut p = Pending::new("base".into());
+        for d in &[0xd, 8, 0, 0] {
+            p.push(*d);
+        }
+        assert_eq!(p.get_char(), None);
+        assert_eq!(p.get_label(), CString::new("U+D800").unwrap());
+    }
+}
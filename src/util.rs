@@ -2,6 +2,7 @@
 use std::rc::Rc;
 
 use crate::float_ord::FloatOrd;
+use serde::{Deserialize, Serialize};
 
 use std::borrow::Borrow;
 use std::cmp::{Ordering, PartialOrd};
@@ -211,7 +212,7 @@ impl DivCeil for i32 {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Rational<T> {
     pub numerator: T,
     pub denominator: u32,
@@ -1,5 +1,8 @@
 /*! Managing the events belonging to virtual-keyboard interface. */
 
+use std::cell::OnceCell;
+use std::ffi::{ CStr, CString };
+
 use crate::keyboard::{ Modifiers, PressType };
 use crate::submission::Timestamp;
 
@@ -69,12 +72,45 @@ pub mod c {
     }
 }
 
+/// Keymap text, turned into a file descriptor for the compositor
+/// the first time it gets used.
+pub struct KeyMap {
+    text: CString,
+    fd: OnceCell<c::KeyMap>,
+}
+
+impl KeyMap {
+    pub fn new(text: CString) -> Self {
+        KeyMap {
+            text,
+            fd: OnceCell::new(),
+        }
+    }
+
+    pub fn get_text(&self) -> &CStr {
+        self.text.as_c_str()
+    }
+
+    fn get_fd(&self) -> &c::KeyMap {
+        self.fd.get_or_init(|| c::KeyMap::from_cstr(self.text.as_c_str()))
+    }
+}
+
+/// Receives the events of the virtual keyboard.
+///
+/// Implemented by `VirtualKeyboard`, and by fakes in tests.
+pub trait Sink {
+    fn switch(&self, keycode: KeyCode, action: PressType, timestamp: Timestamp);
+    fn set_modifiers_state(&self, modifiers: Modifiers);
+    fn update_keymap(&self, keymap: &KeyMap);
+}
+
 /// Layout-independent backend. TODO: Have one instance per program or seat
 pub struct VirtualKeyboard(pub c::ZwpVirtualKeyboardV1);
 
-impl VirtualKeyboard {
+impl Sink for VirtualKeyboard {
     // TODO: error out if keymap not set
-    pub fn switch(
+    fn switch(
         &self,
         keycode: KeyCode,
         action: PressType,
@@ -88,18 +124,18 @@ impl VirtualKeyboard {
         }
     }
     
-    pub fn set_modifiers_state(&self, modifiers: Modifiers) {
+    fn set_modifiers_state(&self, modifiers: Modifiers) {
         let modifiers = modifiers.bits() as u32;
         unsafe {
             c::eek_virtual_keyboard_set_modifiers(self.0, modifiers);
         }
     }
     
-    pub fn update_keymap(&self, keymap: &c::KeyMap) {
+    fn update_keymap(&self, keymap: &KeyMap) {
         unsafe {
             c::eek_virtual_keyboard_update_keymap(
                 self.0,
-                keymap as *const c::KeyMap,
+                keymap.get_fd() as *const c::KeyMap,
             );
         }
     }